@@ -1,98 +1,393 @@
 use std::collections::HashMap;
 
+use crate::romanization::{RomajiSettings, RomajiSystem};
+
+const VOWELS: &str = "aeiou";
+// the long form of each vowel above
+const MACRONS: [char; 5] = ['ā', 'ē', 'ī', 'ō', 'ū'];
+const CIRCUMFLEXES: [char; 5] = ['â', 'ê', 'î', 'ô', 'û'];
+const SMALL_KANA: &str = "ぁぃぅぇぉゃゅょァィゥェォャュョ";
+const SOKUON: [&str; 2] = ["っ", "ッ"];
+/// How a `っ` is typed when there's no consonant after it to double.
+const LONE_SOKUON: [&str; 2] = ["xtsu", "ltsu"];
+const IME_SOKUON: [&str; 2] = ["xtu", "ltu"];
+const SMALL_PREFIXES: [&str; 2] = ["x", "l"];
+const ITERATION_MARK: char = '々';
+const LONG_VOWEL_MARK: &str = "ー";
+// a long word read every way in every system could be spelled thousands of ways
+const MAX_WORD_SPELLINGS: usize = 32;
+
+/// How a line is annotated for learners.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reading {
+    /// Hiragana over the kanji.
+    Kana,
+    /// The whole line in romaji.
+    Romaji,
+}
+
+/// A run of a line typed as one unit, like `きゃ`, `った` or a kanji, with every romaji
+/// that types it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RomajiSegment {
+    /// Where the run starts in the line, in characters.
+    pub at: usize,
+    pub symbol: String,
+    pub spellings: Vec<String>,
+}
+
+impl RomajiSegment {
+    /// How many characters of the line the run covers.
+    fn len(&self) -> usize {
+        self.symbol.chars().count()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct CharController {
     // search_map:HashMap<String,Vec<String>>,
     play_map: HashMap<String, Vec<String>>,
+    // in characters, so matching never tries anything longer
+    longest_key: usize,
+    // words of more than one character and their kana readings
+    words: HashMap<String, Vec<String>>,
+    longest_word: usize,
+    // Hepburn syllables back to the hiragana they spell
+    kana: HashMap<String, String>,
 }
 
 impl CharController {
     pub async fn init(
         //search_chars:Vec<String>,
         play_chars: Vec<&str>,
+        dictionaries: Vec<&str>,
     ) -> Self {
         let mut play_map: HashMap<String, Vec<String>> = HashMap::new();
         for file in play_chars {
             load_chars(&mut play_map, file).await;
         }
-        Self { play_map }
+        let mut words: HashMap<String, Vec<String>> = HashMap::new();
+        for file in dictionaries {
+            load_words(&mut words, file);
+        }
+        let longest = |map: &HashMap<String, Vec<String>>| {
+            map.keys().map(|v| v.chars().count()).max().unwrap_or(1)
+        };
+        // sorted so the plain kana wins, じ over ぢ and お over を
+        let mut syllables: Vec<(&String, &Vec<String>)> = play_map
+            .iter()
+            .filter(|(k, _)| {
+                k.chars().all(|v| ('ぁ'..='ゖ').contains(&v))
+                    && !(k.chars().count() == 1 && SMALL_KANA.contains(k.as_str()))
+            })
+            .collect();
+        syllables.sort();
+        let mut kana: HashMap<String, String> = HashMap::new();
+        for (key, spellings) in syllables {
+            for spelling in spellings.iter().filter(|v| !v.contains('(')) {
+                kana.entry(spelling.clone()).or_insert(key.clone());
+            }
+        }
+        Self {
+            longest_key: longest(&play_map),
+            longest_word: longest(&words),
+            play_map,
+            words,
+            kana,
+        }
     }
 
     pub fn get_play_char(&self, key: &str) -> Option<Vec<String>> {
-        match self.play_map.get(key) {
-            Some(v) => Some(v.clone()),
-            None => None,
-        }
-    }
-
-    pub fn check_special_char(&self, key: &str) -> Option<Vec<String>> {
-        if key.chars().nth(0) == Some('っ') {
-            let k = key.chars().nth(1).unwrap_or(' ').to_string();
-            match self.play_map.get(k.as_str()) {
-                Some(v) => {
-                    if let Some(romaji) = v.get(0) {
-                        Some(vec![romaji.chars().enumerate().fold(
-                            "".into(),
-                            |mut out, (i, v)| {
-                                if i == 0 {
-                                    out.push(v);
-                                    out.push(v);
-                                    out
-                                } else {
-                                    out.push(v);
-                                    out
-                                }
-                            },
-                        )])
-                    } else {
-                        None
-                    }
+        self.play_map.get(key).cloned()
+    }
+
+    /// Turns a whole line into the runs of it that are typed as romaji, in order. Anything
+    /// without romaji, like spaces or latin letters, is left out and typed as it is.
+    ///
+    /// Runs are matched longest first and spelled in every system `settings` allows, with
+    /// the hint's spelling first. The ones that depend on their neighbours are then joined
+    /// to them back to front: `っ` doubles the consonant after it (`tch` before `ch`), `ん`
+    /// takes `nn` or `n'` before a vowel, `y` or `n` so it can't be read as the start of the
+    /// next kana, and `ー` lengthens the vowel before it.
+    pub fn romaji_segments(&self, line: &str, settings: &RomajiSettings) -> Vec<RomajiSegment> {
+        let ime = settings.is_allowed(RomajiSystem::Ime);
+        let chars: Vec<char> = line.chars().collect();
+        let mut runs: Vec<RomajiSegment> = vec![];
+        let mut i = 0;
+        while i < chars.len() {
+            match self.match_at(&chars, i, runs.last(), settings) {
+                Some(run) => {
+                    i += run.len();
+                    runs.push(run);
                 }
-                None => None,
+                None => i += 1,
             }
-        } else {
-            match key.chars().nth(1) {
-                Some('ゃ' | 'ャ' | 'ょ' | 'ョ' | 'ゅ' | 'ュ') => {
-                    let k1 = key.chars().nth(0).unwrap_or(' ').to_string();
-                    let k2 = key.chars().nth(1).unwrap_or(' ').to_string();
-                    if let Some(a) = self.get_play_char(k1.as_str()) {
-                        if let Some(b) = self.get_play_char(k2.as_str()) {
-                            let romaji = a
-                                .iter()
-                                .nth(0)
-                                .unwrap_or(&" ".to_owned())
-                                .chars()
-                                .zip(b.iter().nth(0).unwrap_or(&" ".to_owned()).chars())
-                                .enumerate()
-                                .fold("".into(), |mut out: String, (i, (c, d))| {
-                                    if i == 0 {
-                                        out.push(c);
-                                        out.push(d);
-                                        out
-                                    } else {
-                                        out.push(d);
-                                        out
-                                    }
-                                });
-                            Some(vec![romaji])
-                        } else {
-                            None
+        }
+
+        let mut segments: Vec<RomajiSegment> = vec![];
+        for run in runs.into_iter().rev() {
+            // the segment straight after this run, if nothing untyped sits between them
+            let next = segments.last().filter(|v| v.at == run.at + run.len());
+            let joined = match run.symbol.as_str() {
+                "っ" | "ッ" => next.map(|v| {
+                    let mut spellings = doubled(&v.spellings);
+                    if ime && !spellings.is_empty() {
+                        // or typed on its own first
+                        for sokuon in LONE_SOKUON.iter().chain(&IME_SOKUON) {
+                            for spelling in &v.spellings {
+                                push_new(&mut spellings, format!("{}{}", sokuon, spelling));
+                            }
                         }
-                    } else {
-                        None
                     }
+                    spellings
+                }),
+                "ん" | "ン" => next.map(|v| after_n(&v.spellings, settings)),
+                _ => next
+                    .filter(|v| v.symbol == LONG_VOWEL_MARK)
+                    .map(|_| lengthened(&run.spellings, settings)),
+            }
+            .filter(|v| !v.is_empty());
+            match joined {
+                Some(spellings) => {
+                    let next = segments.pop().unwrap_or_default();
+                    segments.push(RomajiSegment {
+                        at: run.at,
+                        symbol: run.symbol + &next.symbol,
+                        spellings,
+                    });
+                }
+                None if SOKUON.contains(&run.symbol.as_str()) => {
+                    let mut spellings: Vec<String> = LONE_SOKUON.map(String::from).to_vec();
+                    if ime {
+                        spellings.extend(IME_SOKUON.map(String::from));
+                    }
+                    segments.push(RomajiSegment { spellings, ..run })
+                }
+                None => segments.push(run),
+            }
+        }
+        segments.reverse();
+        segments
+    }
+
+    /// The line with its kanji read out in hiragana, or all of it spelled out in romaji
+    /// in the hint's system, for annotating it. Anything without a reading is left as it is.
+    pub fn reading(&self, line: &str, reading: Reading, settings: &RomajiSettings) -> String {
+        if reading == Reading::Kana {
+            // read as one, so `々` repeats the kana before it even across segments
+            return self.kana_of(line);
+        }
+        let chars: Vec<char> = line.chars().collect();
+        let mut out = String::new();
+        let mut i = 0;
+        for segment in self.romaji_segments(line, settings) {
+            out.extend(&chars[i..segment.at]);
+            out.push_str(segment.spellings.first().unwrap_or(&segment.symbol));
+            i = segment.at + segment.len();
+        }
+        out.extend(&chars[i..]);
+        out
+    }
+
+    /// Whether any of the line has a reading, so it's worth annotating.
+    pub fn has_readings(&self, line: &str) -> bool {
+        line.chars()
+            .any(|v| v == ITERATION_MARK || self.play_map.contains_key(&v.to_string()))
+    }
+
+    /// `text` with its kanji in hiragana, read as words from the dictionary or else by
+    /// each kanji's first reading.
+    fn kana_of(&self, text: &str) -> String {
+        let chars: Vec<char> = text.chars().collect();
+        let mut out = String::new();
+        let mut last = String::new();
+        let mut i = 0;
+        while i < chars.len() {
+            let word = (2..=self.longest_word.min(chars.len() - i))
+                .rev()
+                .find_map(|len| {
+                    let word: String = chars[i..i + len].iter().collect();
+                    let reading = self.words.get(&word)?.first()?;
+                    Some((len, reading.clone()))
+                });
+            let (len, kana) = match word {
+                Some(word) => word,
+                None if chars[i] == ITERATION_MARK => (1, last.clone()),
+                None if is_kanji(chars[i]) => (
+                    1,
+                    self.hepburn(&chars[i].to_string())
+                        .and_then(|v| self.to_kana(&v[0]))
+                        .unwrap_or_else(|| chars[i].to_string()),
+                ),
+                None => (1, chars[i].to_string()),
+            };
+            out.push_str(&kana);
+            last = kana;
+            i += len;
+        }
+        out
+    }
+
+    /// Hiragana for a Hepburn reading like the kanji maps have, if all of it can be read.
+    fn to_kana(&self, romaji: &str) -> Option<String> {
+        let chars: Vec<char> = romaji.chars().collect();
+        let mut out = String::new();
+        let mut i = 0;
+        while i < chars.len() {
+            let next = chars.get(i + 1).copied();
+            // a doubled consonant, or the t of tch, is a っ
+            if !VOWELS.contains(chars[i])
+                && chars[i] != 'n'
+                && (next == Some(chars[i]) || (chars[i] == 't' && next == Some('c')))
+            {
+                out.push('っ');
+                i += 1;
+                continue;
+            }
+            if chars[i] == 'n' && next == Some('\'') {
+                out.push('ん');
+                i += 2;
+                continue;
+            }
+            let (len, kana) = (1..=3.min(chars.len() - i)).rev().find_map(|len| {
+                let syllable: String = chars[i..i + len].iter().collect();
+                self.kana.get(&syllable).map(|v| (len, v))
+            })?;
+            out.push_str(kana);
+            i += len;
+        }
+        Some(out)
+    }
+
+    /// The longest run at `i` with romaji, with small kana after it folded in.
+    fn match_at(
+        &self,
+        chars: &[char],
+        i: usize,
+        previous: Option<&RomajiSegment>,
+        settings: &RomajiSettings,
+    ) -> Option<RomajiSegment> {
+        if chars[i] == ITERATION_MARK {
+            // reads like the kanji before it
+            return previous
+                .filter(|v| v.at + v.len() == i)
+                .map(|v| RomajiSegment {
+                    at: i,
+                    symbol: ITERATION_MARK.into(),
+                    spellings: v.spellings.clone(),
+                });
+        }
+        if SOKUON.contains(&chars[i].to_string().as_str()) {
+            // spelled once the run after it is known
+            return Some(RomajiSegment {
+                at: i,
+                symbol: chars[i].into(),
+                spellings: vec![],
+            });
+        }
+        // whole words first, so compounds get their real reading
+        for len in (2..=self.longest_word.min(chars.len() - i)).rev() {
+            let word: String = chars[i..i + len].iter().collect();
+            let Some(readings) = self.words.get(&word) else {
+                continue;
+            };
+            let spellings = self.spell_readings(readings, settings);
+            if !spellings.is_empty() {
+                return Some(RomajiSegment {
+                    at: i,
+                    symbol: word,
+                    spellings,
+                });
+            }
+        }
+        let ime = settings.is_allowed(RomajiSystem::Ime);
+        for len in (1..=self.longest_key.min(chars.len() - i)).rev() {
+            let symbol: String = chars[i..i + len].iter().collect();
+            let Some(hepburn) = self.hepburn(&symbol) else {
+                continue;
+            };
+            let mut small = symbol.chars().nth(1).filter(|v| SMALL_KANA.contains(*v));
+            let mut symbol = symbol;
+            // combinations the maps don't list, like ティ or ヴァ
+            let mut spellings = None;
+            if len == 1
+                && let Some(next) = chars.get(i + 1)
+                && SMALL_KANA.contains(*next)
+                && let Some(small_hepburn) = self.hepburn(&next.to_string())
+            {
+                let combined = combined(&hepburn, &small_hepburn);
+                if !combined.is_empty() {
+                    small = Some(*next);
+                    symbol.push(*next);
+                    spellings = Some(settings.spell(&combined));
                 }
-                Some('々') => {
-                    let k = key.chars().nth(0).unwrap_or(' ').to_string();
-                    if let Some(v) = self.get_play_char(k.as_str()) {
-                        Some(v.iter().map(|v| v.to_owned() + v).collect())
-                    } else {
-                        None
+            }
+            let mut spellings = spellings.unwrap_or_else(|| settings.spell(&hepburn));
+            if ime {
+                if let Some(small) = small {
+                    // the small kana typed on its own after the one before it
+                    let base = self.hepburn(&chars[i].to_string()).unwrap_or_default();
+                    for base in settings.spell(&base) {
+                        for small in self.small_spellings(small) {
+                            push_new(&mut spellings, format!("{}{}", base, small));
+                        }
+                    }
+                } else if len == 1 && SMALL_KANA.contains(chars[i]) {
+                    for small in self.small_spellings(chars[i]) {
+                        push_new(&mut spellings, small);
                     }
                 }
-                _ => None,
+            }
+            return Some(RomajiSegment {
+                at: i,
+                symbol,
+                spellings,
+            });
+        }
+        None
+    }
+
+    /// The romaji in the maps for `key`, leaving out placeholders like `(geminate)`.
+    fn hepburn(&self, key: &str) -> Option<Vec<String>> {
+        let spellings: Vec<String> = self
+            .get_play_char(key)?
+            .into_iter()
+            .filter(|v| !v.contains('('))
+            .collect();
+        (!spellings.is_empty()).then_some(spellings)
+    }
+
+    /// Every romaji for a word read as any of the kana `readings`, the hint's spellings
+    /// first. Readings with anything that has no romaji are left out.
+    fn spell_readings(&self, readings: &[String], settings: &RomajiSettings) -> Vec<String> {
+        let mut out = vec![];
+        for reading in readings {
+            let segments = self.romaji_segments(reading, settings);
+            if segments.iter().map(|v| v.len()).sum::<usize>() != reading.chars().count() {
+                continue;
+            }
+            let mut spellings = vec![String::new()];
+            for segment in segments {
+                spellings = spellings
+                    .iter()
+                    .flat_map(|start| segment.spellings.iter().map(move |v| start.clone() + v))
+                    .take(MAX_WORD_SPELLINGS)
+                    .collect();
+            }
+            for spelling in spellings {
+                push_new(&mut out, spelling);
             }
         }
+        out
+    }
+
+    /// How IMEs type a small kana on its own, like `xa` or `lya`.
+    fn small_spellings(&self, small: char) -> Vec<String> {
+        let hepburn = self.hepburn(&small.to_string()).unwrap_or_default();
+        SMALL_PREFIXES
+            .iter()
+            .flat_map(|prefix| hepburn.iter().map(move |v| format!("{}{}", prefix, v)))
+            .collect()
     }
 
     // fn convert_search_str(&self,start:String)->String{
@@ -100,6 +395,159 @@ impl CharController {
     // }
 }
 
+/// Every spelling of the run after a `っ` with its first consonant doubled, like `kka` or
+/// `tchi`. Empty when the run doesn't start with a consonant.
+fn doubled(spellings: &[String]) -> Vec<String> {
+    let mut out = vec![];
+    for spelling in spellings {
+        let Some(first) = spelling.chars().next() else {
+            continue;
+        };
+        if spelling.starts_with("ch") {
+            push_new(&mut out, format!("t{}", spelling));
+            push_new(&mut out, format!("c{}", spelling));
+        } else if first.is_ascii_alphabetic() && !VOWELS.contains(first) && first != 'n' {
+            push_new(&mut out, format!("{}{}", first, spelling));
+        }
+    }
+    out
+}
+
+/// Every spelling of a run with `ー` after it: the vowel typed twice, with a macron in
+/// Hepburn or a circumflex in Kunrei and Nihon, or followed by `-` like IMEs take it.
+fn lengthened(spellings: &[String], settings: &RomajiSettings) -> Vec<String> {
+    let mut out = vec![];
+    for spelling in spellings {
+        let Some(vowel) = spelling.chars().last().filter(|v| VOWELS.contains(*v)) else {
+            push_new(&mut out, format!("{}-", spelling));
+            continue;
+        };
+        let stem = &spelling[..spelling.len() - 1];
+        let index = VOWELS.find(vowel).unwrap_or_default();
+        push_new(&mut out, format!("{}{}", spelling, vowel));
+        if settings.is_allowed(RomajiSystem::Hepburn) {
+            push_new(&mut out, format!("{}{}", stem, MACRONS[index]));
+        }
+        if settings.is_allowed(RomajiSystem::Kunrei) || settings.is_allowed(RomajiSystem::Nihon) {
+            push_new(&mut out, format!("{}{}", stem, CIRCUMFLEXES[index]));
+        }
+        push_new(&mut out, format!("{}-", spelling));
+    }
+    out
+}
+
+/// Every spelling of `ん` joined to the run after it. Before a vowel or `y` it's `n'`,
+/// before anything else a single `n`, as in `konnichi`. IMEs also take `nn` anywhere, and
+/// `n'` before `n`.
+fn after_n(spellings: &[String], settings: &RomajiSettings) -> Vec<String> {
+    let typed = spellings
+        .iter()
+        .filter(|v| v.starts_with(|c: char| c.is_ascii_alphabetic()));
+    let mut out = vec![];
+    for spelling in typed.clone() {
+        if spelling.starts_with(|c: char| VOWELS.contains(c) || c == 'y') {
+            push_new(&mut out, format!("n'{}", spelling));
+        } else {
+            push_new(&mut out, format!("n{}", spelling));
+        }
+    }
+    if settings.is_allowed(RomajiSystem::Ime) {
+        for spelling in typed {
+            push_new(&mut out, format!("nn{}", spelling));
+            if spelling.starts_with('n') {
+                push_new(&mut out, format!("n'{}", spelling));
+            }
+        }
+    }
+    out
+}
+
+/// Spellings of a kana and the small kana after it typed together, like `ti` for ティ,
+/// `fa` for ファ, `wi` for ウィ or `kwa` for クァ.
+fn combined(bases: &[String], smalls: &[String]) -> Vec<String> {
+    let mut out = vec![];
+    for base in bases {
+        let consonant = base.trim_end_matches(|v| VOWELS.contains(v));
+        for small in smalls {
+            let spelling = match (consonant, small.strip_prefix('y')) {
+                ("", Some(_)) => continue,
+                (consonant, Some(vowel))
+                    if consonant.ends_with("sh")
+                        || consonant.ends_with("ch")
+                        || consonant.ends_with('j') =>
+                {
+                    format!("{}{}", consonant, vowel)
+                }
+                (consonant, Some(_)) => format!("{}{}", consonant, small),
+                ("", None) => match base.as_str() {
+                    "u" => format!("w{}", small),
+                    "i" => format!("y{}", small),
+                    _ => continue,
+                },
+                ("k" | "g", None) if base.ends_with('u') => format!("{}w{}", consonant, small),
+                (consonant, None) => format!("{}{}", consonant, small),
+            };
+            push_new(&mut out, spelling);
+        }
+    }
+    out
+}
+
+fn push_new(out: &mut Vec<String>, spelling: String) {
+    if !out.contains(&spelling) {
+        out.push(spelling);
+    }
+}
+
+/// The dictionary at `WORD_DICTIONARY`, if one is set, to read words by along with the
+/// bundled one. It can be a JMdict derived EDICT file saved as UTF-8.
+pub fn dictionary_from_env() -> Option<String> {
+    let path = std::env::var("WORD_DICTIONARY")
+        .ok()
+        .filter(|v| !v.is_empty())?;
+    match std::fs::read_to_string(&path) {
+        Ok(source) => Some(source),
+        Err(e) => {
+            log::error!("word dictionary {} failed to load: {}", path, e);
+            None
+        }
+    }
+}
+
+/// Adds the words of an EDICT style dictionary, one `WORD;WORD [READING;READING] /.../`
+/// entry a line, to `map`. Only words with a kanji and more than one character are kept,
+/// since single kanji are in the kanji maps.
+fn load_words(map: &mut HashMap<String, Vec<String>>, source: &str) {
+    let without_tags = |v: &str| v.split('(').next().unwrap_or_default().trim().to_owned();
+    for line in source.lines() {
+        let Some((heads, rest)) = line.split_once(' ') else {
+            continue;
+        };
+        let Some(readings) = rest
+            .strip_prefix('[')
+            .and_then(|v| v.split_once(']'))
+            .map(|v| v.0)
+        else {
+            // kana words need no reading
+            continue;
+        };
+        let readings: Vec<String> = readings.split(';').map(without_tags).collect();
+        for head in heads.split(';').map(without_tags) {
+            if head.chars().count() < 2 || !head.chars().any(is_kanji) {
+                continue;
+            }
+            let known = map.entry(head).or_default();
+            for reading in &readings {
+                push_new(known, reading.clone());
+            }
+        }
+    }
+}
+
+fn is_kanji(c: char) -> bool {
+    ('\u{4e00}'..='\u{9fff}').contains(&c) || c == ITERATION_MARK
+}
+
 async fn load_chars(map: &mut HashMap<String, Vec<String>>, json: &str) {
     match serde_json::from_str::<HashMap<String, Vec<String>>>(json) {
         Ok(v) => {
@@ -110,3 +558,120 @@ async fn load_chars(map: &mut HashMap<String, Vec<String>>, json: &str) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spellings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    fn controller() -> CharController {
+        iced::futures::executor::block_on(CharController::init(
+            vec![
+                include_str!("../assets/kana_maps/hiragana.json"),
+                include_str!("../assets/kana_maps/kanji-joyo.json"),
+            ],
+            vec![include_str!("../assets/dictionaries/common.edict")],
+        ))
+    }
+
+    fn without_ime() -> RomajiSettings {
+        let mut settings = RomajiSettings::default();
+        settings.toggle(RomajiSystem::Ime);
+        settings
+    }
+
+    #[test]
+    fn doubled_repeats_the_consonant() {
+        assert_eq!(doubled(&spellings(&["ka"])), spellings(&["kka"]));
+        assert_eq!(doubled(&spellings(&["chi"])), spellings(&["tchi", "cchi"]));
+        assert!(doubled(&spellings(&["a", "na", ""])).is_empty());
+    }
+
+    #[test]
+    fn after_n_takes_a_single_n_before_n() {
+        let settings = without_ime();
+        assert_eq!(after_n(&spellings(&["ni"]), &settings), spellings(&["nni"]));
+        assert_eq!(after_n(&spellings(&["ka"]), &settings), spellings(&["nka"]));
+        assert_eq!(after_n(&spellings(&["a"]), &settings), spellings(&["n'a"]));
+        assert_eq!(
+            after_n(&spellings(&["ya"]), &settings),
+            spellings(&["n'ya"])
+        );
+    }
+
+    #[test]
+    fn after_n_keeps_ime_spellings_after_the_hint() {
+        let settings = RomajiSettings::default();
+        assert_eq!(
+            after_n(&spellings(&["ni"]), &settings),
+            spellings(&["nni", "nnni", "n'ni"])
+        );
+        assert_eq!(
+            after_n(&spellings(&["a"]), &settings),
+            spellings(&["n'a", "nna"])
+        );
+        assert_eq!(
+            after_n(&spellings(&["ka"]), &settings),
+            spellings(&["nka", "nnka"])
+        );
+    }
+
+    #[test]
+    fn lengthened_spells_long_vowels() {
+        let settings = RomajiSettings::default();
+        assert_eq!(
+            lengthened(&spellings(&["ryu"]), &settings),
+            spellings(&["ryuu", "ryū", "ryû", "ryu-"])
+        );
+        let mut settings = RomajiSettings::default();
+        settings.toggle(RomajiSystem::Kunrei);
+        settings.toggle(RomajiSystem::Nihon);
+        assert_eq!(
+            lengthened(&spellings(&["ko"]), &settings),
+            spellings(&["koo", "kō", "ko-"])
+        );
+        assert_eq!(
+            lengthened(&spellings(&["n"]), &settings),
+            spellings(&["n-"])
+        );
+    }
+
+    #[test]
+    fn combined_joins_small_kana() {
+        assert_eq!(
+            combined(&spellings(&["te"]), &spellings(&["i"])),
+            spellings(&["ti"])
+        );
+        assert_eq!(
+            combined(&spellings(&["shi"]), &spellings(&["ya"])),
+            spellings(&["sha"])
+        );
+        assert_eq!(
+            combined(&spellings(&["ki"]), &spellings(&["ya"])),
+            spellings(&["kya"])
+        );
+        assert_eq!(
+            combined(&spellings(&["u"]), &spellings(&["i"])),
+            spellings(&["wi"])
+        );
+        assert_eq!(
+            combined(&spellings(&["ku"]), &spellings(&["a"])),
+            spellings(&["kwa"])
+        );
+        assert!(combined(&spellings(&["a"]), &spellings(&["ya"])).is_empty());
+    }
+
+    #[test]
+    fn kana_reading_repeats_the_kana_before_the_iteration_mark() {
+        let cc = controller();
+        let settings = RomajiSettings::default();
+        assert_eq!(cc.reading("山々", Reading::Kana, &settings), "やまやま");
+        assert_eq!(
+            cc.reading("人々と山々", Reading::Kana, &settings),
+            "ひとびととやまやま"
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+};
+
+use crate::storage;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// One finished session, as it is kept on disk.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SessionRecord {
+    /// Unix seconds.
+    pub finished_at: u64,
+    pub source: String,
+    pub song_id: Option<String>,
+    pub title: Option<String>,
+    pub wpm: f32,
+    /// From 0 to 1.
+    pub accuracy: f32,
+    pub errors: usize,
+    pub lines: usize,
+    /// Seconds spent typing.
+    pub duration: f32,
+    pub score: usize,
+}
+
+/// Every finished session, appended one JSON line at a time under the data dir.
+#[derive(Debug, Clone)]
+pub struct History {
+    path: PathBuf,
+}
+
+impl History {
+    pub fn open() -> anyhow::Result<Self> {
+        Ok(Self {
+            path: storage::data_dir()?.join("history.jsonl"),
+        })
+    }
+
+    pub fn append(&self, record: &SessionRecord) -> anyhow::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(record)?)?;
+        Ok(())
+    }
+
+    /// Every session in the order they were finished. Lines that fail to parse, like one
+    /// cut short by a crash, are skipped.
+    pub fn load(&self) -> Vec<SessionRecord> {
+        let Ok(source) = fs::read_to_string(&self.path) else {
+            return vec![];
+        };
+        source
+            .lines()
+            .filter(|v| !v.trim().is_empty())
+            .filter_map(|v| match serde_json::from_str(v) {
+                Ok(record) => Some(record),
+                Err(e) => {
+                    log::warn!("skipping a session in the history: {}", e);
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum TrendPeriod {
+    #[default]
+    Day,
+    Week,
+}
+
+/// The sessions of one day or week rolled together.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trend {
+    pub label: String,
+    pub sessions: usize,
+    /// Averaged over the time spent typing, so long sessions count for more.
+    pub wpm: f32,
+    pub accuracy: f32,
+    pub errors: usize,
+    pub duration: f32,
+    pub score: usize,
+}
+
+/// Groups the sessions by day or by week (starting Monday), oldest first.
+///
+/// Days are in UTC since the records don't keep a time zone.
+pub fn trends(records: &[SessionRecord], period: TrendPeriod) -> Vec<Trend> {
+    let mut trends: Vec<(u64, Vec<&SessionRecord>)> = vec![];
+    for record in records {
+        let day = record.finished_at / SECONDS_PER_DAY;
+        // day 0 was a Thursday
+        let start = match period {
+            TrendPeriod::Day => day,
+            TrendPeriod::Week => day.saturating_sub((day + 3) % 7),
+        };
+        match trends.iter_mut().find(|(v, _)| *v == start) {
+            Some((_, group)) => group.push(record),
+            None => trends.push((start, vec![record])),
+        }
+    }
+    trends.sort_by_key(|(start, _)| *start);
+    trends
+        .into_iter()
+        .map(|(start, group)| {
+            let duration: f32 = group.iter().map(|v| v.duration).sum();
+            let average = |value: fn(&SessionRecord) -> f32| {
+                if duration > 0.0 {
+                    group.iter().map(|v| value(v) * v.duration).sum::<f32>() / duration
+                } else {
+                    group.iter().map(|v| value(v)).sum::<f32>() / group.len() as f32
+                }
+            };
+            let date = format_date(start);
+            Trend {
+                label: match period {
+                    TrendPeriod::Day => date,
+                    TrendPeriod::Week => format!("Week of {}", date),
+                },
+                sessions: group.len(),
+                wpm: average(|v| v.wpm),
+                accuracy: average(|v| v.accuracy),
+                errors: group.iter().map(|v| v.errors).sum(),
+                duration,
+                score: group.iter().map(|v| v.score).sum(),
+            }
+        })
+        .collect()
+}
+
+/// Days since the Unix epoch as `YYYY-MM-DD`.
+fn format_date(days: u64) -> String {
+    // Howard Hinnant's civil_from_days
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(day: u64, wpm: f32) -> SessionRecord {
+        SessionRecord {
+            finished_at: day * SECONDS_PER_DAY + 12 * 60 * 60,
+            source: "Lyrics".into(),
+            song_id: None,
+            title: None,
+            wpm,
+            accuracy: 1.0,
+            errors: 0,
+            lines: 1,
+            duration: 60.0,
+            score: 10,
+        }
+    }
+
+    #[test]
+    fn dates_cross_leap_days_and_years() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(10_957), "2000-01-01");
+        assert_eq!(format_date(19_782), "2024-02-29");
+        assert_eq!(format_date(19_783), "2024-03-01");
+        assert_eq!(format_date(20_088), "2024-12-31");
+        assert_eq!(format_date(20_089), "2025-01-01");
+    }
+
+    #[test]
+    fn weeks_start_on_monday() {
+        // Sunday 2023-12-31, then Monday and Tuesday 2024-01-01 and 02
+        let records = [
+            record(19_722, 30.0),
+            record(19_723, 40.0),
+            record(19_724, 60.0),
+        ];
+        let weeks = trends(&records, TrendPeriod::Week);
+        let labels: Vec<&str> = weeks.iter().map(|v| v.label.as_str()).collect();
+        assert_eq!(labels, ["Week of 2023-12-25", "Week of 2024-01-01"]);
+        assert_eq!(weeks[0].sessions, 1);
+        assert_eq!(weeks[1].sessions, 2);
+        assert_eq!(weeks[1].wpm, 50.0);
+        assert_eq!(weeks[1].score, 20);
+    }
+
+    #[test]
+    fn days_are_grouped_oldest_first() {
+        let records = [
+            record(19_783, 30.0),
+            record(19_782, 40.0),
+            record(19_782, 50.0),
+        ];
+        let days = trends(&records, TrendPeriod::Day);
+        let labels: Vec<&str> = days.iter().map(|v| v.label.as_str()).collect();
+        assert_eq!(labels, ["2024-02-29", "2024-03-01"]);
+        assert_eq!(days[0].sessions, 2);
+    }
+}
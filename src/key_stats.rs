@@ -0,0 +1,174 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use crate::storage;
+
+/// Gaps longer than this are the typist pausing, not the time a key took.
+const MAX_LATENCY: Duration = Duration::from_secs(2);
+
+/// How one key, or one pair of keys, has been typed.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct KeyStat {
+    pub hits: usize,
+    pub misses: usize,
+    total_latency: Duration,
+    timed: usize,
+}
+
+impl KeyStat {
+    pub fn presses(&self) -> usize {
+        self.hits + self.misses
+    }
+
+    /// From 0 to 1.
+    pub fn error_rate(&self) -> f32 {
+        if self.presses() == 0 {
+            0.0
+        } else {
+            self.misses as f32 / self.presses() as f32
+        }
+    }
+
+    /// The average time from the key before, if it was ever typed in a run.
+    pub fn mean_latency(&self) -> Option<Duration> {
+        if self.timed == 0 {
+            None
+        } else {
+            Some(self.total_latency / self.timed as u32)
+        }
+    }
+
+    fn record(&mut self, hit: bool, latency: Option<Duration>) {
+        if hit {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+        if let Some(latency) = latency {
+            self.total_latency += latency;
+            self.timed += 1;
+        }
+    }
+}
+
+/// Per-key and per-bigram speed and accuracy, built up one keystroke at a time.
+///
+/// Keys are the ones that should have been pressed, so a miss counts against the key the
+/// typist was aiming for. Letters are counted without case.
+///
+/// They're saved under the data dir, so what the typist has learned, and the lesson
+/// letters it unlocked, carry over between runs.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct KeyStats {
+    keys: HashMap<char, KeyStat>,
+    bigrams: HashMap<String, KeyStat>,
+    // the key before and when it was pressed, while the typist is in a run
+    #[serde(skip)]
+    last: Option<(char, Instant)>,
+}
+
+fn path() -> anyhow::Result<PathBuf> {
+    Ok(storage::data_dir()?.join("key_stats.json"))
+}
+
+impl KeyStats {
+    /// The stats saved by the last run, or none if nothing has been typed yet.
+    pub fn load() -> anyhow::Result<Self> {
+        let path = path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let path = path()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn record(&mut self, key: char, hit: bool, at: Instant) {
+        let key = key.to_lowercase().next().unwrap_or(key);
+        let latency = self
+            .last
+            .map(|(_, last_at)| at.saturating_duration_since(last_at))
+            .filter(|v| *v <= MAX_LATENCY);
+        self.keys.entry(key).or_default().record(hit, latency);
+        if let Some((last_key, _)) = self.last
+            && latency.is_some()
+        {
+            self.bigrams
+                .entry(format!("{}{}", last_key, key))
+                .or_default()
+                .record(hit, latency);
+        }
+        self.last = Some((key, at));
+    }
+
+    /// Stops the run, so the wait before the next key isn't counted as its latency.
+    pub fn break_run(&mut self) {
+        self.last = None;
+    }
+
+    pub fn key(&self, key: char) -> Option<&KeyStat> {
+        self.keys.get(&key)
+    }
+
+    /// The average latency over every timed key.
+    pub fn mean_latency(&self) -> Option<Duration> {
+        let timed: usize = self.keys.values().map(|v| v.timed).sum();
+        if timed == 0 {
+            return None;
+        }
+        let total: Duration = self.keys.values().map(|v| v.total_latency).sum();
+        Some(total / timed as u32)
+    }
+
+    /// How much trouble a key gives, from 0 for none to 1 for the worst.
+    ///
+    /// Misses count more than being slow, and slowness is measured against the typist's
+    /// own average so the scale works at any speed.
+    pub fn difficulty(&self, key: char) -> Option<f32> {
+        let stat = self.keys.get(&key)?;
+        let slowness = match (stat.mean_latency(), self.mean_latency()) {
+            (Some(latency), Some(mean)) if !mean.is_zero() => {
+                (latency.as_secs_f32() / mean.as_secs_f32() - 1.0).clamp(0.0, 1.0)
+            }
+            _ => 0.0,
+        };
+        Some((stat.error_rate() * 4.0).max(slowness).min(1.0))
+    }
+
+    /// The bigrams with the highest average latency, slowest first.
+    pub fn slowest_bigrams(&self, count: usize) -> Vec<(String, KeyStat)> {
+        let mut bigrams: Vec<(String, KeyStat)> = self
+            .bigrams
+            .iter()
+            .filter(|(_, v)| v.mean_latency().is_some())
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        bigrams.sort_by_key(|(_, v)| std::cmp::Reverse(v.mean_latency()));
+        bigrams.truncate(count);
+        bigrams
+    }
+
+    /// The keys missed most often, worst first.
+    pub fn most_missed(&self, count: usize) -> Vec<(char, KeyStat)> {
+        let mut keys: Vec<(char, KeyStat)> = self
+            .keys
+            .iter()
+            .filter(|(_, v)| v.misses > 0)
+            .map(|(k, v)| (*k, v.clone()))
+            .collect();
+        keys.sort_by(|a, b| b.1.error_rate().total_cmp(&a.1.error_rate()));
+        keys.truncate(count);
+        keys
+    }
+}
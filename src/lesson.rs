@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+
+use crate::key_stats::KeyStats;
+use crate::rng::Rng;
+
+/// Letters are unlocked in this order, most common first.
+const LETTER_ORDER: &str = "etaoinshrdlcumwfgypbvkjxqz";
+const STARTING_LETTERS: usize = 6;
+// every unlocked letter needs this many presses at this accuracy before the next one
+const UNLOCK_PRESSES: usize = 20;
+const UNLOCK_ACCURACY: f32 = 0.95;
+const WORDS_PER_LINE: usize = 6;
+// with fewer real words than this the lines would repeat too much
+const MIN_REAL_WORDS: usize = 20;
+const WORD_LIST: &str = include_str!("../assets/word_lists/english.txt");
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum LessonWords {
+    /// Made up words that read like English.
+    #[default]
+    Pseudo,
+    /// Words from the bundled list, when enough of them can be typed with the letters so far.
+    Real,
+}
+
+/// Practice text made from the letters unlocked so far, leaning on the ones that give the
+/// typist the most trouble.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lesson {
+    words: LessonWords,
+    // the unlocked letters in unlock order, with how often each should come up
+    weights: Vec<(char, f32)>,
+    focus: char,
+}
+
+impl Lesson {
+    /// Unlocks letters keybr-style and weighs them by their difficulty in `stats`.
+    ///
+    /// A new letter unlocks once every letter before it has been pressed enough times
+    /// accurately enough. Letters never typed count as hard, and the newest letter gets a
+    /// boost so it is learned quickly.
+    pub fn from_stats(stats: &KeyStats, words: LessonWords) -> Self {
+        let order: Vec<char> = LETTER_ORDER.chars().collect();
+        let mut unlocked = STARTING_LETTERS;
+        while unlocked < order.len()
+            && order[..unlocked].iter().all(|v| {
+                stats.key(*v).is_some_and(|stat| {
+                    stat.presses() >= UNLOCK_PRESSES && 1.0 - stat.error_rate() >= UNLOCK_ACCURACY
+                })
+            })
+        {
+            unlocked += 1;
+        }
+
+        let mut weights: Vec<(char, f32)> = order[..unlocked]
+            .iter()
+            .map(|v| (*v, 1.0 + 4.0 * stats.difficulty(*v).unwrap_or(1.0)))
+            .collect();
+        if unlocked > STARTING_LETTERS
+            && let Some((_, weight)) = weights.last_mut()
+        {
+            *weight += 2.0;
+        }
+        let focus = weights
+            .iter()
+            .rev()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|v| v.0)
+            .unwrap_or('e');
+        Self {
+            words,
+            weights,
+            focus,
+        }
+    }
+
+    pub fn words(&self) -> LessonWords {
+        self.words
+    }
+
+    /// The unlocked letters, in the order they were unlocked.
+    pub fn letters(&self) -> String {
+        self.weights.iter().map(|v| v.0).collect()
+    }
+
+    /// The letter the lesson leans on the most.
+    pub fn focus(&self) -> char {
+        self.focus
+    }
+
+    /// Makes `count` lines of words, each ending in a space like every other text.
+    pub fn generate_lines(&self, count: usize) -> Vec<String> {
+        let mut rng = Rng::from_clock();
+        let real_words = match self.words {
+            LessonWords::Real => self.real_words(),
+            LessonWords::Pseudo => vec![],
+        };
+        let model = (real_words.len() < MIN_REAL_WORDS).then(|| self.bigram_model());
+        (0..count)
+            .map(|_| {
+                let words: Vec<String> = (0..WORDS_PER_LINE)
+                    .map(|_| match &model {
+                        Some(model) => self.pseudo_word(model, &mut rng),
+                        None => {
+                            let weights: Vec<f32> = real_words.iter().map(|v| v.1).collect();
+                            real_words[rng.pick_weighted(&weights)].0.to_owned()
+                        }
+                    })
+                    .collect();
+                words.join(" ") + " "
+            })
+            .collect()
+    }
+
+    fn weight(&self, letter: char) -> Option<f32> {
+        self.weights.iter().find(|v| v.0 == letter).map(|v| v.1)
+    }
+
+    /// The listed words made only of unlocked letters, weighted by their average letter.
+    fn real_words(&self) -> Vec<(&'static str, f32)> {
+        WORD_LIST
+            .lines()
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+            .filter_map(|word| {
+                let weights: Option<Vec<f32>> = word.chars().map(|v| self.weight(v)).collect();
+                let weights = weights?;
+                Some((word, weights.iter().sum::<f32>() / weights.len() as f32))
+            })
+            .collect()
+    }
+
+    /// How likely each unlocked letter is to follow another in the word list, with `' '`
+    /// standing for the start of a word.
+    fn bigram_model(&self) -> HashMap<char, HashMap<char, f32>> {
+        let mut counts: HashMap<char, HashMap<char, usize>> = HashMap::new();
+        for word in WORD_LIST.lines() {
+            let mut last = ' ';
+            for letter in word.trim().chars() {
+                if self.weight(letter).is_none() {
+                    // the rest of the word can't be typed yet
+                    break;
+                }
+                *counts.entry(last).or_default().entry(letter).or_default() += 1;
+                last = letter;
+            }
+        }
+        counts
+            .into_iter()
+            .map(|(last, next)| {
+                let total: usize = next.values().sum();
+                let next = next
+                    .into_iter()
+                    .map(|(k, v)| (k, v as f32 / total as f32))
+                    .collect();
+                (last, next)
+            })
+            .collect()
+    }
+
+    fn pseudo_word(&self, model: &HashMap<char, HashMap<char, f32>>, rng: &mut Rng) -> String {
+        let length = 3 + rng.below(5);
+        let mut word = String::new();
+        let mut last = ' ';
+        for _ in 0..length {
+            // a little weight on every pair keeps unseen ones possible
+            let weights: Vec<f32> = self
+                .weights
+                .iter()
+                .map(|(letter, weight)| {
+                    let chance = model
+                        .get(&last)
+                        .and_then(|v| v.get(letter))
+                        .copied()
+                        .unwrap_or(0.0);
+                    (chance + 0.02) * weight
+                })
+                .collect();
+            last = self.weights[rng.pick_weighted(&weights)].0;
+            word.push(last);
+        }
+        word
+    }
+}
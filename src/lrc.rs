@@ -0,0 +1,301 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// How long the final line of a synced file stays on screen, since nothing follows it.
+const LAST_LINE_LENGTH: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LrcLine {
+    pub time: Duration,
+    pub text: String,
+}
+
+/// The part of the song a line is sung in, from its own timestamp up to the next one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SyncWindow {
+    pub start: Duration,
+    pub end: Duration,
+}
+
+/// Where the song is, running with the clock while it plays and held still while paused.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SongClock {
+    // the song position at `anchor`
+    offset: Duration,
+    anchor: Instant,
+    playing: bool,
+}
+
+impl SongClock {
+    /// A clock playing from `position` at `now`.
+    pub fn starting_at(position: Duration, now: Instant) -> Self {
+        Self {
+            offset: position,
+            anchor: now,
+            playing: true,
+        }
+    }
+
+    pub fn position(&self, now: Instant) -> Duration {
+        if self.playing {
+            self.offset + now.saturating_duration_since(self.anchor)
+        } else {
+            self.offset
+        }
+    }
+
+    /// Holds the clock where it is until it's resumed.
+    pub fn pause(&mut self, now: Instant) {
+        self.offset = self.position(now);
+        self.anchor = now;
+        self.playing = false;
+    }
+
+    /// A clock at `position` as of `at`, running or held still as the player is.
+    pub fn synced(position: Duration, playing: bool, at: Instant) -> Self {
+        Self {
+            offset: position,
+            anchor: at,
+            playing,
+        }
+    }
+
+    /// Carries on from where the clock was paused.
+    pub fn resume(&mut self, now: Instant) {
+        if !self.playing {
+            self.anchor = now;
+            self.playing = true;
+        }
+    }
+}
+
+/// A parsed LRC file: its `[key:value]` tags and its timed lines.
+#[derive(Debug, Clone, Default)]
+pub struct LrcFile {
+    pub tags: HashMap<String, String>,
+    pub lines: Vec<LrcLine>,
+}
+
+impl LrcFile {
+    /// Parses an LRC file, sorted by time with the `[offset:]` tag already applied.
+    ///
+    /// A line can carry several timestamps (`[00:12.00][01:30.00]chorus`) and is repeated
+    /// at each of them. Enhanced `<mm:ss.xx>` word stamps are stripped from the text and
+    /// lines without a timestamp are ignored.
+    pub fn parse(source: &str) -> Self {
+        let mut tags = HashMap::new();
+        let mut lines = vec![];
+        for line in source.lines() {
+            let mut rest = line.trim();
+            let mut times = vec![];
+            while let Some(inner) = rest.strip_prefix('[') {
+                let Some((tag, after)) = inner.split_once(']') else {
+                    break;
+                };
+                if let Some(time) = parse_timestamp(tag) {
+                    times.push(time);
+                } else if let Some((key, value)) = tag.split_once(':') {
+                    tags.insert(key.trim().to_lowercase(), value.trim().to_string());
+                } else {
+                    break;
+                }
+                rest = after.trim_start();
+            }
+            if times.is_empty() {
+                continue;
+            }
+            let text = strip_word_stamps(rest);
+            for time in times {
+                lines.push(LrcLine {
+                    time,
+                    text: text.clone(),
+                });
+            }
+        }
+
+        // a positive offset means the lyrics should come up sooner
+        let offset: i64 = tags
+            .get("offset")
+            .and_then(|v| v.trim_start_matches('+').parse().ok())
+            .unwrap_or(0);
+        for line in &mut lines {
+            line.time = if offset >= 0 {
+                line.time
+                    .saturating_sub(Duration::from_millis(offset as u64))
+            } else {
+                line.time + Duration::from_millis(offset.unsigned_abs())
+            };
+        }
+        lines.sort_by_key(|v| v.time);
+        Self { tags, lines }
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.tags.get("ti").map(|v| v.as_str())
+    }
+
+    pub fn artist(&self) -> Option<&str> {
+        self.tags.get("ar").map(|v| v.as_str())
+    }
+}
+
+/// Parses the timed lines of an LRC file, sorted by time.
+pub fn parse_lrc(source: &str) -> Vec<LrcLine> {
+    LrcFile::parse(source).lines
+}
+
+fn strip_word_stamps(text: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        match rest[start..].find('>') {
+            Some(end) if parse_timestamp(&rest[start + 1..start + end]).is_some() => {
+                out.push_str(&rest[..start]);
+                rest = &rest[start + end + 1..];
+            }
+            _ => {
+                out.push_str(&rest[..=start]);
+                rest = &rest[start + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Parses `mm:ss`, `mm:ss.xx` or `mm:ss.xxx` into a duration.
+pub fn parse_timestamp(stamp: &str) -> Option<Duration> {
+    let (minutes, seconds) = stamp.trim().split_once(':')?;
+    let minutes: u64 = minutes.parse().ok()?;
+    let (seconds, fraction) = match seconds.split_once('.') {
+        Some((s, f)) => (s, f),
+        None => (seconds, ""),
+    };
+    let seconds: u64 = seconds.parse().ok()?;
+    if seconds >= 60 {
+        return None;
+    }
+    let millis = if fraction.is_empty() {
+        0
+    } else {
+        // .x is tenths, .xx hundredths and .xxx milliseconds
+        let digits: String = fraction.chars().take(3).collect();
+        let value: u64 = digits.parse().ok()?;
+        value * 10u64.pow(3 - digits.len() as u32)
+    };
+    Some(Duration::from_millis(
+        (minutes * 60 + seconds) * 1000 + millis,
+    ))
+}
+
+/// Turns parsed lines into typeable lyrics with their windows.
+///
+/// Empty lines are dropped from the lyrics but still close the window of the line before
+/// them, so instrumental breaks don't count as time to type.
+pub fn into_synced_lyrics(lines: &[LrcLine]) -> (Vec<String>, Vec<SyncWindow>) {
+    let mut lyrics = vec![];
+    let mut windows = vec![];
+    for (i, line) in lines.iter().enumerate() {
+        if line.text.is_empty() {
+            continue;
+        }
+        let end = match lines.get(i + 1) {
+            Some(next) if next.time > line.time => next.time,
+            _ => line.time + LAST_LINE_LENGTH,
+        };
+        lyrics.push(line.text.clone() + " ");
+        windows.push(SyncWindow {
+            start: line.time,
+            end,
+        });
+    }
+    (lyrics, windows)
+}
+
+/// How far from the end of its window a line can be finished and still count as on time.
+const ON_TIME_MARGIN: Duration = Duration::from_millis(750);
+/// How long after its window closes a line can still be finished before it's missed.
+pub const MISS_AFTER: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncGrade {
+    Early(Duration),
+    OnTime,
+    Late(Duration),
+    Missed,
+}
+
+impl SyncGrade {
+    /// Grades a line finished at `finished` (song time) against the end of its window,
+    /// which is when the singer moves on to the next line.
+    pub fn grade(window: SyncWindow, finished: Duration) -> Self {
+        if finished + ON_TIME_MARGIN < window.end {
+            SyncGrade::Early(window.end - finished)
+        } else if finished <= window.end + ON_TIME_MARGIN {
+            SyncGrade::OnTime
+        } else if finished <= window.end + MISS_AFTER {
+            SyncGrade::Late(finished - window.end)
+        } else {
+            SyncGrade::Missed
+        }
+    }
+}
+
+impl std::fmt::Display for SyncGrade {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SyncGrade::Early(d) => write!(f, "Early by {:.1}s", d.as_secs_f32()),
+            SyncGrade::OnTime => write!(f, "On time"),
+            SyncGrade::Late(d) => write!(f, "Late by {:.1}s", d.as_secs_f32()),
+            SyncGrade::Missed => write!(f, "Missed"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(seconds: f32) -> Duration {
+        Duration::from_secs_f32(seconds)
+    }
+
+    #[test]
+    fn a_line_repeats_at_each_of_its_timestamps() {
+        let file = LrcFile::parse("[00:12.00][01:30.50]chorus\n[00:20.00]verse");
+        let lines: Vec<(Duration, &str)> = file
+            .lines
+            .iter()
+            .map(|v| (v.time, v.text.as_str()))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                (secs(12.0), "chorus"),
+                (secs(20.0), "verse"),
+                (secs(90.5), "chorus")
+            ]
+        );
+    }
+
+    #[test]
+    fn offset_moves_every_line() {
+        let sooner = LrcFile::parse("[offset:+500]\n[00:00.20]a\n[00:10.00]b");
+        assert_eq!(sooner.lines[0].time, Duration::ZERO);
+        assert_eq!(sooner.lines[1].time, secs(9.5));
+        let later = LrcFile::parse("[offset:-250]\n[00:10.00]b");
+        assert_eq!(later.lines[0].time, secs(10.25));
+    }
+
+    #[test]
+    fn tag_only_lines_are_tags_not_lyrics() {
+        let file = LrcFile::parse("[ti:Song]\n[ar:Someone]\n[length: 03:20]\n[00:01.00]hello");
+        assert_eq!(file.title(), Some("Song"));
+        assert_eq!(file.artist(), Some("Someone"));
+        assert_eq!(file.tags.get("length").map(|v| v.as_str()), Some("03:20"));
+        assert_eq!(file.lines.len(), 1);
+        assert_eq!(file.lines[0].text, "hello");
+    }
+}
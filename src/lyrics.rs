@@ -0,0 +1,190 @@
+use crate::lrc::{self, SyncWindow};
+use crate::spotify_controller::Song;
+
+const TRACK_WEIGHT: f32 = 4.0;
+const ARTIST_WEIGHT: f32 = 3.0;
+const DURATION_WEIGHT: f32 = 2.0;
+const ALBUM_WEIGHT: f32 = 1.0;
+const SYNCED_BONUS: f32 = 0.5;
+/// A score at least this high is trusted without asking the next provider.
+pub const GOOD_MATCH: f32 = 0.6;
+/// Durations this close (in seconds) are treated as the same recording.
+const DURATION_EXACT: f32 = 2.0;
+/// Durations further apart than this (in seconds) don't count as a match at all.
+const DURATION_FAR: f32 = 15.0;
+
+/// What we know about the song we want lyrics for.
+#[derive(Debug, Clone, Default)]
+pub struct LyricsQuery {
+    /// The Spotify track id, if the song came from Spotify.
+    pub id: Option<String>,
+    pub track: String,
+    pub artist: String,
+    pub album: Option<String>,
+    /// In seconds.
+    pub duration: Option<f32>,
+}
+
+impl From<Song> for LyricsQuery {
+    fn from(song: Song) -> Self {
+        Self {
+            id: Some(song.id),
+            track: song.name,
+            artist: song.artist,
+            album: song.album,
+            duration: song.duration_ms.map(|v| v as f32 / 1000.0),
+        }
+    }
+}
+
+/// One set of lyrics a provider offered for a query.
+#[derive(Debug, Clone)]
+pub struct LyricsCandidate {
+    pub track: String,
+    pub artist: String,
+    pub album: String,
+    /// In seconds.
+    pub duration: f32,
+    pub plain_lyrics: Option<String>,
+    pub synced_lyrics: Option<String>,
+    /// The name of the provider this came from.
+    pub provider: String,
+    pub score: f32,
+}
+
+/// The parts of a candidate the UI lists, without the lyrics themselves.
+#[derive(Debug, Clone)]
+pub struct CandidateSummary {
+    pub track: String,
+    pub artist: String,
+    pub album: String,
+    pub duration: f32,
+    pub synced: bool,
+    pub provider: String,
+    pub score: f32,
+}
+
+impl LyricsCandidate {
+    pub fn has_lyrics(&self) -> bool {
+        self.plain_lyrics.is_some() || self.synced_lyrics.is_some()
+    }
+
+    pub fn summary(&self) -> CandidateSummary {
+        CandidateSummary {
+            track: self.track.clone(),
+            artist: self.artist.clone(),
+            album: self.album.clone(),
+            duration: self.duration,
+            synced: self.synced_lyrics.is_some(),
+            provider: self.provider.clone(),
+            score: self.score,
+        }
+    }
+
+    /// The lyrics to type, with windows if `synced` is asked for.
+    ///
+    /// Plain lyrics fall back to the text of the synced ones, but synced lyrics can't be
+    /// made up from plain ones.
+    pub fn lyrics(&self, synced: bool) -> Option<(Vec<String>, Vec<SyncWindow>)> {
+        if synced {
+            let synced_lyrics = self.synced_lyrics.as_ref()?;
+            return Some(lrc::into_synced_lyrics(&lrc::parse_lrc(synced_lyrics)));
+        }
+        match &self.plain_lyrics {
+            Some(plain_lyrics) => Some((
+                plain_lyrics
+                    .split('\n')
+                    .filter(|v| !v.is_empty())
+                    .map(|v| v.to_owned() + " ")
+                    .collect(),
+                vec![],
+            )),
+            None => {
+                let synced_lyrics = self.synced_lyrics.as_ref()?;
+                Some((
+                    lrc::into_synced_lyrics(&lrc::parse_lrc(synced_lyrics)).0,
+                    vec![],
+                ))
+            }
+        }
+    }
+}
+
+/// Scores every candidate against the query and sorts them best first.
+///
+/// Candidates without any lyrics (instrumentals) are dropped.
+pub fn rank_candidates(
+    query: &LyricsQuery,
+    candidates: Vec<LyricsCandidate>,
+) -> Vec<LyricsCandidate> {
+    let mut ranked: Vec<LyricsCandidate> = candidates
+        .into_iter()
+        .filter(|v| v.has_lyrics())
+        .map(|mut v| {
+            v.score = score_candidate(query, &v);
+            v
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.score.total_cmp(&a.score));
+    ranked
+}
+
+/// How well a candidate matches, from 0 to 1 with a little extra for synced lyrics.
+///
+/// Only the fields the query actually has count towards the score.
+pub fn score_candidate(query: &LyricsQuery, candidate: &LyricsCandidate) -> f32 {
+    let mut score = TRACK_WEIGHT * text_similarity(&query.track, &candidate.track)
+        + ARTIST_WEIGHT * text_similarity(&query.artist, &candidate.artist);
+    let mut total = TRACK_WEIGHT + ARTIST_WEIGHT;
+    if let Some(duration) = query.duration {
+        let diff = (duration - candidate.duration).abs();
+        score += DURATION_WEIGHT
+            * ((DURATION_FAR - diff.max(DURATION_EXACT)) / (DURATION_FAR - DURATION_EXACT))
+                .clamp(0.0, 1.0);
+        total += DURATION_WEIGHT;
+    }
+    if let Some(album) = &query.album {
+        score += ALBUM_WEIGHT * text_similarity(album, &candidate.album);
+        total += ALBUM_WEIGHT;
+    }
+    if candidate.synced_lyrics.is_some() {
+        score += SYNCED_BONUS;
+    }
+    score / total
+}
+
+/// 1 for the same words, less for containment or partly shared words, 0 for nothing shared.
+fn text_similarity(a: &str, b: &str) -> f32 {
+    let a = normalize(a);
+    let b = normalize(b);
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    if a == b {
+        return 1.0;
+    }
+    if a.contains(&b) || b.contains(&a) {
+        return 0.8;
+    }
+    let a_words: Vec<&str> = a.split(' ').collect();
+    let b_words: Vec<&str> = b.split(' ').collect();
+    let shared = a_words.iter().filter(|v| b_words.contains(v)).count();
+    let all = a_words.len() + b_words.len() - shared;
+    0.7 * shared as f32 / all as f32
+}
+
+/// Lowercase words without punctuation, so "Mr. Brightside" and "mr brightside" match.
+fn normalize(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            if c.is_alphanumeric() {
+                c.to_lowercase().next().unwrap_or(c)
+            } else {
+                ' '
+            }
+        })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
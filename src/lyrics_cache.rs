@@ -0,0 +1,316 @@
+use std::{fs, path::PathBuf};
+
+use crate::lyrics::{LyricsCandidate, LyricsQuery};
+use crate::storage;
+
+pub const DEFAULT_MAX_BYTES: u64 = 5 * 1024 * 1024;
+/// What cached songs list as their provider.
+pub const CACHE_PROVIDER: &str = "Cache";
+
+/// Lyrics we've fetched before, one JSON file per song under the data dir.
+#[derive(Debug, Clone)]
+pub struct LyricsCache {
+    dir: PathBuf,
+    // the size limit is kept beside the cache, so it holds across runs
+    limit_path: PathBuf,
+    max_bytes: u64,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct CachedLyrics {
+    key: String,
+    song_id: Option<String>,
+    track: String,
+    artist: String,
+    album: String,
+    duration: f32,
+    plain_lyrics: Option<String>,
+    synced_lyrics: Option<String>,
+    cached_at: u64,
+    last_used: u64,
+}
+
+/// A cached song as the UI lists it.
+#[derive(Debug, Clone)]
+pub struct CachedSong {
+    pub key: String,
+    pub song_id: Option<String>,
+    pub track: String,
+    pub artist: String,
+    pub album: String,
+    pub duration: f32,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CacheListing {
+    pub songs: Vec<CachedSong>,
+    pub bytes: u64,
+    pub max_bytes: u64,
+}
+
+/// The Spotify track id when there is one, otherwise the track signature.
+pub fn cache_key(query: &LyricsQuery) -> String {
+    match &query.id {
+        Some(id) if !id.is_empty() => format!("spotify-{}", id),
+        _ => signature_key(query),
+    }
+}
+
+fn signature_key(query: &LyricsQuery) -> String {
+    format!(
+        "sig-{}-{}",
+        query.artist.trim().to_lowercase(),
+        query.track.trim().to_lowercase()
+    )
+}
+
+impl LyricsCache {
+    /// The cache under the data dir, with the size limit it was last given.
+    pub fn open() -> anyhow::Result<Self> {
+        let data_dir = storage::data_dir()?;
+        let limit_path = data_dir.join("lyrics_cache_limit");
+        let max_bytes = fs::read_to_string(&limit_path)
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(DEFAULT_MAX_BYTES);
+        Ok(Self {
+            dir: data_dir.join("lyrics_cache"),
+            limit_path,
+            max_bytes,
+        })
+    }
+
+    pub fn set_max_bytes(&mut self, max_bytes: u64) {
+        self.max_bytes = max_bytes;
+        let result = fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::write(&self.limit_path, max_bytes.to_string()));
+        if let Err(e) = result {
+            log::error!("could not save the lyrics cache limit: {}", e);
+        }
+        self.evict_to(max_bytes);
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(storage::file_stem(key) + ".json")
+    }
+
+    /// Finds the song by track id or by signature, and marks it as just used.
+    pub fn get(&self, query: &LyricsQuery) -> Option<LyricsCandidate> {
+        let mut entry = self
+            .read(&cache_key(query))
+            .or_else(|| self.read(&signature_key(query)))?;
+        entry.last_used = storage::unix_now();
+        self.write(&entry);
+        Some(LyricsCandidate {
+            track: entry.track,
+            artist: entry.artist,
+            album: entry.album,
+            duration: entry.duration,
+            plain_lyrics: entry.plain_lyrics,
+            synced_lyrics: entry.synced_lyrics,
+            provider: CACHE_PROVIDER.into(),
+            score: 1.0,
+        })
+    }
+
+    pub fn put(&self, query: &LyricsQuery, candidate: &LyricsCandidate) {
+        let now = storage::unix_now();
+        let entry = CachedLyrics {
+            key: cache_key(query),
+            song_id: query.id.clone().filter(|v| !v.is_empty()),
+            track: query.track.clone(),
+            artist: query.artist.clone(),
+            album: query
+                .album
+                .clone()
+                .unwrap_or_else(|| candidate.album.clone()),
+            duration: query.duration.unwrap_or(candidate.duration),
+            plain_lyrics: candidate.plain_lyrics.clone(),
+            synced_lyrics: candidate.synced_lyrics.clone(),
+            cached_at: now,
+            last_used: now,
+        };
+        self.write(&entry);
+        self.evict_to(self.max_bytes);
+    }
+
+    pub fn remove(&self, key: &str) {
+        if let Err(e) = fs::remove_file(self.path(key)) {
+            log::warn!("could not remove cached lyrics {}: {}", key, e);
+        }
+    }
+
+    pub fn clear(&self) {
+        for (entry, _) in self.entries() {
+            self.remove(&entry.key);
+        }
+    }
+
+    /// Drops the least recently used songs until the cache fits in `max_bytes`.
+    pub fn evict_to(&self, max_bytes: u64) {
+        let mut entries = self.entries();
+        let mut bytes: u64 = entries.iter().map(|(_, size)| size).sum();
+        entries.sort_by_key(|(entry, _)| entry.last_used);
+        for (entry, size) in entries {
+            if bytes <= max_bytes {
+                break;
+            }
+            self.remove(&entry.key);
+            bytes -= size;
+        }
+    }
+
+    pub fn listing(&self) -> CacheListing {
+        let mut entries = self.entries();
+        entries.sort_by(|(a, _), (b, _)| (&a.artist, &a.track).cmp(&(&b.artist, &b.track)));
+        CacheListing {
+            bytes: entries.iter().map(|(_, size)| size).sum(),
+            songs: entries
+                .into_iter()
+                .map(|(entry, bytes)| CachedSong {
+                    key: entry.key,
+                    song_id: entry.song_id,
+                    track: entry.track,
+                    artist: entry.artist,
+                    album: entry.album,
+                    duration: entry.duration,
+                    bytes,
+                })
+                .collect(),
+            max_bytes: self.max_bytes,
+        }
+    }
+
+    fn read(&self, key: &str) -> Option<CachedLyrics> {
+        let source = fs::read_to_string(self.path(key)).ok()?;
+        match serde_json::from_str(&source) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                log::error!("cached lyrics {} failed to load: {}", key, e);
+                None
+            }
+        }
+    }
+
+    fn write(&self, entry: &CachedLyrics) {
+        let result = fs::create_dir_all(&self.dir).and_then(|_| {
+            fs::write(
+                self.path(&entry.key),
+                serde_json::to_string(entry).unwrap_or_default(),
+            )
+        });
+        if let Err(e) = result {
+            log::error!("could not cache lyrics {}: {}", entry.key, e);
+        }
+    }
+
+    fn entries(&self) -> Vec<(CachedLyrics, u64)> {
+        let Ok(dir) = fs::read_dir(&self.dir) else {
+            return vec![];
+        };
+        dir.filter_map(Result::ok)
+            .filter_map(|file| {
+                let size = file.metadata().ok()?.len();
+                let source = fs::read_to_string(file.path()).ok()?;
+                let entry: CachedLyrics = serde_json::from_str(&source).ok()?;
+                Some((entry, size))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A cache of its own in the temp dir, emptied first.
+    fn cache(name: &str) -> LyricsCache {
+        let dir = std::env::temp_dir().join(format!(
+            "iced_musical_typing-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        LyricsCache {
+            limit_path: dir.join("limit"),
+            dir: dir.join("lyrics_cache"),
+            max_bytes: DEFAULT_MAX_BYTES,
+        }
+    }
+
+    fn query(id: Option<&str>, track: &str) -> LyricsQuery {
+        LyricsQuery {
+            id: id.map(String::from),
+            track: track.into(),
+            artist: "Artist".into(),
+            album: None,
+            duration: Some(180.0),
+        }
+    }
+
+    fn candidate(lyrics: &str) -> LyricsCandidate {
+        LyricsCandidate {
+            track: "Track".into(),
+            artist: "Artist".into(),
+            album: "Album".into(),
+            duration: 180.0,
+            plain_lyrics: Some(lyrics.into()),
+            synced_lyrics: None,
+            provider: "LRCLIB".into(),
+            score: 0.8,
+        }
+    }
+
+    #[test]
+    fn put_songs_are_found_by_id_or_signature() {
+        let cache = cache("get");
+        assert!(cache.get(&query(Some("abc"), "Song")).is_none());
+        cache.put(&query(Some("abc"), "Song"), &candidate("la la"));
+        let found = cache.get(&query(Some("abc"), "Song")).unwrap();
+        assert_eq!(found.plain_lyrics.as_deref(), Some("la la"));
+        assert_eq!(found.provider, CACHE_PROVIDER);
+        assert!(cache.get(&query(Some("xyz"), "Other")).is_none());
+
+        cache.put(&query(None, "Song"), &candidate("na na"));
+        let found = cache.get(&query(Some("new-id"), " song ")).unwrap();
+        assert_eq!(found.plain_lyrics.as_deref(), Some("na na"));
+    }
+
+    #[test]
+    fn remove_forgets_a_song() {
+        let cache = cache("remove");
+        let song = query(Some("abc"), "Song");
+        cache.put(&song, &candidate("la la"));
+        cache.remove(&cache_key(&song));
+        assert!(cache.get(&song).is_none());
+        assert!(cache.listing().songs.is_empty());
+    }
+
+    #[test]
+    fn eviction_drops_the_least_recently_used_first() {
+        let cache = cache("evict");
+        let old = query(Some("old"), "Old");
+        let new = query(Some("new"), "New");
+        cache.put(&old, &candidate("la la"));
+        cache.put(&new, &candidate("na na"));
+        let mut entry = cache.read(&cache_key(&old)).unwrap();
+        entry.last_used = 1;
+        cache.write(&entry);
+
+        let listing = cache.listing();
+        let newest = listing.songs.iter().find(|v| v.key == cache_key(&new));
+        cache.evict_to(newest.unwrap().bytes);
+        assert!(cache.get(&old).is_none());
+        assert!(cache.get(&new).is_some());
+    }
+
+    #[test]
+    fn the_size_limit_is_kept_and_applied() {
+        let mut cache = cache("limit");
+        cache.put(&query(Some("abc"), "Song"), &candidate("la la"));
+        cache.set_max_bytes(0);
+        assert!(cache.listing().songs.is_empty());
+        assert_eq!(fs::read_to_string(&cache.limit_path).unwrap(), "0");
+    }
+}
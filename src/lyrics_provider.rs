@@ -0,0 +1,300 @@
+use std::{fmt::Debug, fs, future::Future, path::PathBuf, pin::Pin, sync::Arc};
+
+use crate::lrc::{self, LrcFile};
+use crate::lyrics::{LyricsCandidate, LyricsQuery};
+
+pub const DEFAULT_LRCLIB_URL: &str = "https://lrclib.net/api";
+
+pub type ProviderFuture<'a, T> = Pin<Box<dyn Future<Output = anyhow::Result<T>> + Send + 'a>>;
+
+/// Somewhere lyrics can come from, like LRCLIB or a folder of `.lrc` files.
+///
+/// Providers only find candidates; ranking them against the song is left to the caller.
+pub trait LyricsProvider: Debug + Send + Sync {
+    fn name(&self) -> &str;
+
+    fn candidates<'a>(&'a self, query: &'a LyricsQuery)
+    -> ProviderFuture<'a, Vec<LyricsCandidate>>;
+}
+
+/// The providers to use, in priority order, from the environment.
+///
+/// `LYRICS_DIR` adds a local folder ahead of LRCLIB, and `LRCLIB_URL` points LRCLIB at
+/// a mirror or a local mock instead of lrclib.net.
+pub fn providers_from_env() -> Vec<Arc<dyn LyricsProvider>> {
+    let mut providers: Vec<Arc<dyn LyricsProvider>> = vec![];
+    if let Ok(dir) = std::env::var("LYRICS_DIR")
+        && !dir.is_empty()
+    {
+        providers.push(Arc::new(LocalDirProvider::new(dir)));
+    }
+    let base_url = std::env::var("LRCLIB_URL").unwrap_or(DEFAULT_LRCLIB_URL.into());
+    providers.push(Arc::new(LrclibProvider::new(base_url)));
+    providers
+}
+
+#[derive(Debug, Clone)]
+pub struct LrclibProvider {
+    base_url: String,
+}
+
+impl LrclibProvider {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+impl LyricsProvider for LrclibProvider {
+    fn name(&self) -> &str {
+        "LRCLIB"
+    }
+
+    fn candidates<'a>(
+        &'a self,
+        query: &'a LyricsQuery,
+    ) -> ProviderFuture<'a, Vec<LyricsCandidate>> {
+        Box::pin(search_lrclib(&self.base_url, query))
+    }
+}
+
+/// A folder of `.lrc` and `.txt` files.
+///
+/// `.lrc` files are matched on their `[ti:]`, `[ar:]`, `[al:]` and `[length:]` tags, and
+/// anything without tags on an `Artist - Title` file name.
+#[derive(Debug, Clone)]
+pub struct LocalDirProvider {
+    dir: PathBuf,
+}
+
+impl LocalDirProvider {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn read_candidates(&self) -> anyhow::Result<Vec<LyricsCandidate>> {
+        let mut candidates = vec![];
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let extension = path
+                .extension()
+                .map(|v| v.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            if extension != "lrc" && extension != "txt" {
+                continue;
+            }
+            let Ok(source) = fs::read_to_string(&path) else {
+                continue;
+            };
+            let stem = path
+                .file_stem()
+                .map(|v| v.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let (file_artist, file_track) = match stem.split_once(" - ") {
+                Some((artist, track)) => (artist.trim().to_string(), track.trim().to_string()),
+                None => ("".into(), stem.trim().to_string()),
+            };
+
+            let candidate = if extension == "lrc" {
+                let lrc_file = LrcFile::parse(&source);
+                let synced = !lrc_file.lines.is_empty();
+                LyricsCandidate {
+                    track: lrc_file.title().map(String::from).unwrap_or(file_track),
+                    artist: lrc_file.artist().map(String::from).unwrap_or(file_artist),
+                    album: lrc_file.tags.get("al").cloned().unwrap_or_default(),
+                    duration: lrc_file
+                        .tags
+                        .get("length")
+                        .and_then(|v| lrc::parse_timestamp(v))
+                        .map(|v| v.as_secs_f32())
+                        .unwrap_or(0.0),
+                    plain_lyrics: if synced {
+                        None
+                    } else {
+                        Some(plain_lines(&source))
+                    },
+                    synced_lyrics: if synced { Some(source) } else { None },
+                    provider: self.name().into(),
+                    score: 0.0,
+                }
+            } else {
+                LyricsCandidate {
+                    track: file_track,
+                    artist: file_artist,
+                    album: "".into(),
+                    duration: 0.0,
+                    plain_lyrics: Some(source),
+                    synced_lyrics: None,
+                    provider: self.name().into(),
+                    score: 0.0,
+                }
+            };
+            candidates.push(candidate);
+        }
+        Ok(candidates)
+    }
+}
+
+/// The lines of an `.lrc` file that has no timestamps, minus its tags.
+fn plain_lines(source: &str) -> String {
+    source
+        .lines()
+        .filter(|v| !(v.trim().starts_with('[') && v.trim().ends_with(']')))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+impl LyricsProvider for LocalDirProvider {
+    fn name(&self) -> &str {
+        "Local Folder"
+    }
+
+    fn candidates<'a>(
+        &'a self,
+        _query: &'a LyricsQuery,
+    ) -> ProviderFuture<'a, Vec<LyricsCandidate>> {
+        let provider = self.clone();
+        Box::pin(async move {
+            // reading the folder blocks, so keep it off the async runtime
+            std::thread::spawn(move || provider.read_candidates())
+                .join()
+                .map_err(|_| anyhow::anyhow!("read lyrics folder thread panicked"))?
+        })
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+
+struct LrclibObj {
+    #[serde(rename = "id")]
+    id: i64,
+    #[serde(rename = "name", default)]
+    _name: String,
+    #[serde(rename = "trackName")]
+    track_name: String,
+    #[serde(rename = "artistName")]
+    artist_name: String,
+    #[serde(rename = "albumName")]
+    album_name: String,
+    #[serde(rename = "duration")]
+    duration: f32,
+    #[serde(rename = "instrumental")]
+    _instrumental: bool,
+    #[serde(rename = "plainLyrics")]
+    plain_lyrics: Option<String>,
+    #[serde(rename = "syncedLyrics")]
+    synced_lyrics: Option<String>,
+}
+
+impl From<LrclibObj> for LyricsCandidate {
+    fn from(obj: LrclibObj) -> Self {
+        Self {
+            track: obj.track_name,
+            artist: obj.artist_name,
+            album: obj.album_name,
+            duration: obj.duration,
+            plain_lyrics: obj.plain_lyrics,
+            synced_lyrics: obj.synced_lyrics,
+            provider: "LRCLIB".into(),
+            score: 0.0,
+        }
+    }
+}
+
+/// Looks the song up by its exact signature and by searching, with the exact hit first.
+///
+/// The search runs even after an exact hit, so there are other candidates to pick from
+/// and something to fall back on when the hit lacks the lyrics the mode needs.
+async fn search_lrclib(
+    base_url: &str,
+    query: &LyricsQuery,
+) -> anyhow::Result<Vec<LyricsCandidate>> {
+    let client = reqwest::Client::new();
+    let exact = match get_lrclib_exact(&client, base_url, query).await {
+        Ok(v) => v,
+        Err(e) => {
+            log::warn!("LRCLIB get failed, searching instead: {}", e);
+            None
+        }
+    };
+
+    let searched = async {
+        let mut map = search_lrclib_with(
+            &client,
+            base_url,
+            &[
+                ("track_name", query.track.as_str()),
+                ("artist_name", query.artist.as_str()),
+            ],
+        )
+        .await?;
+        if map.is_empty() {
+            let q = query.track.clone() + " " + query.artist.as_str();
+            map = search_lrclib_with(&client, base_url, &[("q", q.as_str())]).await?;
+        }
+        anyhow::Ok(map)
+    }
+    .await;
+    let searched = match (searched, &exact) {
+        (Ok(map), _) => map,
+        (Err(e), Some(_)) => {
+            log::warn!("LRCLIB search failed, keeping the exact match: {}", e);
+            vec![]
+        }
+        (Err(e), None) => return Err(e),
+    };
+
+    let mut map: Vec<LrclibObj> = exact.into_iter().collect();
+    for obj in searched {
+        if !map.iter().any(|v| v.id == obj.id) {
+            map.push(obj);
+        }
+    }
+    Ok(map.into_iter().map(LyricsCandidate::from).collect())
+}
+
+/// The `/get` endpoint needs the whole signature, so without album and duration it's skipped.
+async fn get_lrclib_exact(
+    client: &reqwest::Client,
+    base_url: &str,
+    query: &LyricsQuery,
+) -> anyhow::Result<Option<LrclibObj>> {
+    let (Some(album), Some(duration)) = (&query.album, query.duration) else {
+        return Ok(None);
+    };
+    let duration = (duration.round() as i64).to_string();
+    let res: reqwest::Response = client
+        .get(base_url.to_owned() + "/get")
+        .query(&[
+            ("track_name", query.track.as_str()),
+            ("artist_name", query.artist.as_str()),
+            ("album_name", album.as_str()),
+            ("duration", duration.as_str()),
+        ])
+        .header("Content-Length", 0)
+        .send()
+        .await?;
+
+    log::debug!("LRCLIB Get Response {:#?}", res.status());
+    if res.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    Ok(Some(res.error_for_status()?.json::<LrclibObj>().await?))
+}
+
+async fn search_lrclib_with(
+    client: &reqwest::Client,
+    base_url: &str,
+    params: &[(&str, &str)],
+) -> anyhow::Result<Vec<LrclibObj>> {
+    let res: reqwest::Response = client
+        .get(base_url.to_owned() + "/search")
+        .query(params)
+        .header("Content-Length", 0)
+        .send()
+        .await?;
+
+    println!("LRCLIB Response {:#?}", res.status());
+    Ok(res.error_for_status()?.json::<Vec<LrclibObj>>().await?)
+}
@@ -1,32 +1,111 @@
 use iced::{
     Element, Font, Length, Subscription, Task, Theme, time,
-    widget::{Column, Row, Space, button, column, row, text, text_input},
+    widget::{Column, Row, Space, button, column, container, row, text, text_input},
     window,
 };
 use image::GenericImageView;
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
 
 mod char_controller;
+mod history;
+mod key_stats;
+mod lesson;
+mod lrc;
+mod lyrics;
+mod lyrics_cache;
+mod lyrics_provider;
+mod replay;
+mod rhythm;
+mod rng;
+mod romanization;
+mod session_mode;
+mod source_tree;
 mod spotify_controller;
+mod storage;
 mod text_controller;
-use char_controller::CharController;
+mod typing_session;
+use char_controller::{CharController, Reading};
+use history::{History, SessionRecord, TrendPeriod};
+use key_stats::KeyStats;
+use lesson::{Lesson, LessonWords};
+use lrc::{SongClock, SyncGrade, SyncWindow};
+use lyrics::{CandidateSummary, LyricsQuery};
+use lyrics_cache::{CACHE_PROVIDER, CacheListing, CachedSong};
+use replay::{Playback, Progress, Recorder, Replay};
+use rhythm::{Judgment, Rhythm};
+use romanization::{RomajiSettings, RomajiSystem};
+use session_mode::{EndReason, Results, SessionMode};
+use source_tree::{FileOrder, IndentMode, IndentStyle, SourceTreeSettings};
 use spotify_controller::Song;
-use spotify_controller::SpotifyController;
+use spotify_controller::{PlaybackState, SpotifyController};
+use typing_session::{CharState, InputResult, Metrics, Pace, Strictness, TypingSession};
 
 use crate::text_controller::TextController;
 
 struct TypingGame {
-    input: String,
+    session: TypingSession,
     query: String,
+    lyrics_path: String,
+    source_tree: SourceTreeSettings,
+    lesson_words: LessonWords,
+    romaji: RomajiSettings,
+    // readings shown over the lines of the page, and the lines set apart from that
+    annotation: Option<Reading>,
+    line_annotations: HashMap<usize, Option<Reading>>,
+    // the lines of the page that have a reading, with the one shown over each
+    readings: HashMap<usize, Option<String>>,
     score: usize,
+    // the score when the session started, so each session keeps only its own points
+    session_start_score: usize,
+    // what the session is typing, for the history
+    session_source: &'static str,
+    session_song_id: Option<String>,
+    history: Vec<SessionRecord>,
+    history_period: TrendPeriod,
+    recorder: Recorder,
+    replaying: Option<Replaying>,
+    replay_path: String,
+    replay_speed: f32,
+    // the replay of the last finished session
+    last_replay: Option<PathBuf>,
+    race_ghost: bool,
+    pace_input: String,
+    target_wpm: Option<f32>,
+    // set off by the first keystroke on each page
+    pace: Option<Pace>,
+    // the best earlier run on the text being typed
+    ghost: Option<Replay>,
     spotify_controller_handle: SpotifyControllerHandle,
     spotify_data: SpotifyData,
     text_controller_handle: TextControllerHandle,
     text_controller_data: TextControllerData,
     char_controller_handle: CharControllerHandle,
-    char_bonus: Option<(String, Vec<String>)>,
+    // where the song is, for synced text
+    song_clock: Option<SongClock>,
+    last_grade: Option<SyncGrade>,
+    rhythm: Rhythm,
+    session_mode: SessionMode,
+    // shown in place of the text once a timed or fixed-length session ends
+    results: Option<Results>,
+    // the last Tick, which the live metrics are measured up to
+    now: Instant,
+    show_key_stats: bool,
+    lyrics_candidates: Vec<CandidateSummary>,
+    cached_songs: CacheListing,
+}
+
+/// A replay shown in the lyric view, with the text being typed put aside until it ends.
+struct Replaying {
+    playback: Playback,
+    // replay time zero, so the replayed session is timed like the real one was
+    origin: Instant,
+    page: usize,
+    saved_text: TextControllerData,
+    saved_session: TypingSession,
 }
 
 enum CharControllerHandle {
@@ -65,18 +144,39 @@ enum TextControllerHandle {
 
 #[derive(Debug, Clone)]
 enum TextType {
-    LRCLIB,
+    Lrclib,
+    SyncedLrclib,
+    LocalFile(PathBuf),
     // Github,
-    ThisProject,
+    SourceTree(SourceTreeSettings),
+    Lesson(Lesson),
+}
+impl TextType {
+    pub fn label(&self) -> &'static str {
+        match self {
+            TextType::Lrclib => "LRCLIB",
+            TextType::SyncedLrclib => "Synced LRCLIB",
+            TextType::LocalFile(_) => "Local File",
+            TextType::SourceTree(_) => "Source File",
+            TextType::Lesson(_) => "Lesson",
+        }
+    }
 }
 #[derive(Debug, Clone)]
 struct TextControllerData {
     pub text_type: TextType,
+    pub title: Option<String>,
     pub lyrics: Vec<String>,
+    // empty unless the lyrics are synced, otherwise one per line
+    pub windows: Vec<SyncWindow>,
     current_line: usize,
     pub next_fetch_line: i32,
 }
 impl TextControllerData {
+    pub fn current_window(&self) -> Option<SyncWindow> {
+        self.windows.get(self.current_line).copied()
+    }
+
     pub fn count_up(&mut self) -> bool {
         self.current_line += 1;
         if self.current_line >= self.lyrics.len() {
@@ -90,13 +190,15 @@ impl TextControllerData {
 impl Default for TextControllerData {
     fn default() -> Self {
         Self {
-            text_type: TextType::ThisProject,
+            text_type: TextType::SourceTree(SourceTreeSettings::default()),
+            title: None,
             lyrics: vec![
                 "First you need to ".into(),
                 "Select a song ".into(),
                 "So get on that ".into(),
                 "Or you won't be able to start! ".into(),
             ],
+            windows: vec![],
             current_line: 0,
             next_fetch_line: 0,
         }
@@ -124,23 +226,94 @@ enum Message {
     HideDevices,
     SpotifySetDevice(String),
     APIResult(String, Result<(), String>),
+    Played(Result<bool, String>),
+    PollPlayback,
+    PlaybackPolled(Result<Option<PlaybackState>, String>),
     DevicesResult(Result<Vec<(String, String)>, String>),
     SpotifyChangeSong(Song),
     SetLRCLIBText,
+    SetSyncedLRCLIBText,
+    LyricsPathChanged(String),
+    SetLocalFileText,
     // SetGithubText,
     SetSourceFileText,
+    SetLessonText,
+    ToggleLessonWords,
+    ToggleRomajiSystem(RomajiSystem),
+    CycleRomajiHint,
+    CycleAnnotation,
+    CycleLineAnnotation(usize),
+    SourceRootChanged(String),
+    SourceExtensionsChanged(String),
+    SourceGlobChanged(String),
+    ToggleSourceOrder,
+    CycleIndentStyle,
+    CycleTabWidth,
+    ToggleIndentMode,
+    CycleStrictness,
+    ToggleKeyStats,
     NextLyricBatch,
     SkipLine,
     LoadNewText,
+    UpdateLyricsCandidates(Vec<CandidateSummary>),
+    ChooseLyricsCandidate(usize),
+    SearchLyricsProviders,
+    ShowLyricsCandidates(Vec<CandidateSummary>),
+    RefreshCache,
+    UpdateCache(CacheListing),
+    PracticeCached(CachedSong),
+    RemoveCached(String),
+    ClearCache,
+    CycleCacheLimit,
     UpdateText(TextControllerData),
     UpdateSongs(Option<Vec<Song>>),
     CheckForeignChars,
+    HistoryLoaded(Vec<SessionRecord>),
+    KeyStatsLoaded(KeyStats),
+    UpdateHistory(Vec<SessionRecord>),
+    ToggleHistoryPeriod,
+    CloseRequested,
+    ReplayPathChanged(String),
+    WatchReplay,
+    WatchLastReplay,
+    StartReplay(Result<Replay, String>),
+    CycleReplaySpeed,
+    StopReplay,
+    ReplaySaved(Option<PathBuf>),
+    ToggleGhost,
+    PaceChanged(String),
+    CycleSessionMode,
+    TextFinished,
+    UpdateGhost(Option<Replay>),
 }
 
 const COMPLETED_COLOR: iced::Color = iced::Color::from_rgb(0.0, 0.5, 0.1);
 const MATCHING_COLOR: iced::Color = iced::Color::from_rgb(0.5, 0.8, 1.0);
 const PREPARE_COLOR: iced::Color = iced::Color::from_rgb(1.0, 1.0, 0.6);
+const ERROR_COLOR: iced::Color = iced::Color::from_rgb(1.0, 0.3, 0.3);
 const UPCOMING_COLOR: iced::Color = iced::Color::from_rgb(1.0, 1.0, 1.0);
+const WAITING_COLOR: iced::Color = iced::Color::from_rgb(0.5, 0.5, 0.5);
+// behind the characters the ghost of the best run and the pace are on
+const GHOST_COLOR: iced::Color = iced::Color::from_rgba(0.7, 0.5, 1.0, 0.5);
+const PERFECT_COLOR: iced::Color = iced::Color::from_rgb(1.0, 0.85, 0.2);
+const PACE_COLOR: iced::Color = iced::Color::from_rgba(1.0, 0.6, 0.2, 0.5);
+// the key heatmap fades from easy to hard, with untyped keys left grey
+const EASY_KEY_COLOR: iced::Color = iced::Color::from_rgb(0.2, 0.55, 0.3);
+const HARD_KEY_COLOR: iced::Color = iced::Color::from_rgb(0.85, 0.2, 0.2);
+const UNTYPED_KEY_COLOR: iced::Color = iced::Color::from_rgb(0.3, 0.3, 0.3);
+
+// how many days or weeks the history shows
+const HISTORY_PERIODS: usize = 8;
+
+const KEYBOARD_ROWS: [&str; 4] = ["1234567890-=", "qwertyuiop[]", "asdfghjkl;'", "zxcvbnm,./"];
+
+// the lyrics cache size limits to cycle through, in bytes
+const CACHE_LIMITS: [u64; 4] = [
+    1024 * 1024,
+    5 * 1024 * 1024,
+    20 * 1024 * 1024,
+    100 * 1024 * 1024,
+];
 
 impl Default for TypingGame {
     fn default() -> Self {
@@ -148,8 +321,68 @@ impl Default for TypingGame {
     }
 }
 
+// how often synced text checks where the song is
+const PLAYBACK_POLL: Duration = Duration::from_secs(1);
+// how many ways to type the symbol at the cursor the info row shows
+const SHOWN_SPELLINGS: usize = 3;
+const ANNOTATION_SIZE: u16 = 12;
+
+/// The reading after `annotation`, going from none to kana to romaji and back.
+fn next_annotation(annotation: Option<Reading>) -> Option<Reading> {
+    match annotation {
+        None => Some(Reading::Kana),
+        Some(Reading::Kana) => Some(Reading::Romaji),
+        Some(Reading::Romaji) => None,
+    }
+}
+
+// shown when a text runs out before anything was typed
+const NOTHING_TO_TYPE: &str = "Nothing left to type here, load something else ";
+
 const MY_FONT: Font = Font::with_name("Noto Sans CJK JP");
 
+/// Lays out the colored runs of a line, with the characters under each marker highlighted
+/// in the marker's color.
+fn marked_row<'a>(
+    runs: Vec<(iced::Color, String)>,
+    markers: &[(usize, iced::Color)],
+) -> Row<'a, Message> {
+    let marker = |under: String, color: iced::Color, background: iced::Color| {
+        container(text(under).style(move |_| text::Style { color: Some(color) })).style(move |_| {
+            container::Style {
+                background: Some(background.into()),
+                ..Default::default()
+            }
+        })
+    };
+    let mut row = Row::new();
+    let mut at = 0;
+    for (color, run) in runs {
+        let style = move |_: &Theme| text::Style { color: Some(color) };
+        let mut plain = String::new();
+        for c in run.chars() {
+            match markers.iter().find(|v| v.0 == at) {
+                Some((_, background)) => {
+                    if !plain.is_empty() {
+                        row = row.push(text(std::mem::take(&mut plain)).style(style));
+                    }
+                    row = row.push(marker(c.to_string(), color, *background));
+                }
+                None => plain.push(c),
+            }
+            at += 1;
+        }
+        if !plain.is_empty() {
+            row = row.push(text(plain).style(style));
+        }
+    }
+    // markers past the end wait after the line
+    for (_, background) in markers.iter().filter(|v| v.0 >= at) {
+        row = row.push(marker(" ".into(), UPCOMING_COLOR, *background));
+    }
+    row
+}
+
 pub fn main() -> iced::Result {
     env_logger::init();
     let full_font = include_bytes!("../assets/fonts/NotoSansCJKjp-Regular.otf").as_slice();
@@ -170,24 +403,78 @@ pub fn main() -> iced::Result {
             ..Default::default()
         })
         .theme(TypingGame::theme)
+        // the session is saved to the history before the window closes
+        .exit_on_close_request(false)
         .run_with(TypingGame::new)
 }
 
 impl TypingGame {
     fn new() -> (Self, Task<Message>) {
+        let mut game = Self {
+            session: TypingSession::default(),
+            query: String::new(),
+            lyrics_path: String::new(),
+            source_tree: SourceTreeSettings::default(),
+            lesson_words: LessonWords::default(),
+            romaji: RomajiSettings::default(),
+            annotation: None,
+            line_annotations: HashMap::new(),
+            readings: HashMap::new(),
+            score: 0,
+            session_start_score: 0,
+            session_source: TextControllerData::default().text_type.label(),
+            session_song_id: None,
+            history: vec![],
+            history_period: TrendPeriod::default(),
+            recorder: Recorder::default(),
+            replaying: None,
+            replay_path: String::new(),
+            replay_speed: 1.0,
+            last_replay: None,
+            race_ghost: true,
+            pace_input: String::new(),
+            target_wpm: None,
+            pace: None,
+            ghost: None,
+            spotify_controller_handle: SpotifyControllerHandle::Loading,
+            spotify_data: SpotifyData::default(),
+            text_controller_handle: TextControllerHandle::Loading,
+            text_controller_data: TextControllerData::default(),
+            char_controller_handle: CharControllerHandle::Loading,
+            song_clock: None,
+            now: Instant::now(),
+            show_key_stats: false,
+            last_grade: None,
+            rhythm: Rhythm::default(),
+            session_mode: SessionMode::default(),
+            results: None,
+            lyrics_candidates: vec![],
+            cached_songs: CacheListing::default(),
+        };
+        // the placeholder text is typed like any other until something is loaded
+        game.start_current_line();
         (
-            Self {
-                input: String::new(),
-                query: String::new(),
-                score: 0,
-                spotify_controller_handle: SpotifyControllerHandle::Loading,
-                spotify_data: SpotifyData::default(),
-                text_controller_handle: TextControllerHandle::Loading,
-                text_controller_data: TextControllerData::default(),
-                char_controller_handle: CharControllerHandle::Loading,
-                char_bonus: None,
-            },
-            Task::done(Message::InitializeStart),
+            game,
+            Task::batch([
+                Task::done(Message::InitializeStart),
+                Task::perform(
+                    async {
+                        History::open()
+                            .map(|v| v.load())
+                            .map_err(|e| log::error!("could not load the history: {}", e))
+                            .unwrap_or_default()
+                    },
+                    Message::HistoryLoaded,
+                ),
+                Task::perform(
+                    async {
+                        KeyStats::load()
+                            .map_err(|e| log::error!("could not load the key stats: {}", e))
+                            .unwrap_or_default()
+                    },
+                    Message::KeyStatsLoaded,
+                ),
+            ]),
         )
     }
 
@@ -220,43 +507,69 @@ impl TypingGame {
                 );
             }
             Message::InputChanged(value) => {
-                self.input = value;
-                if self.input.trim().is_empty() {
-                    self.input = "".into();
-                }
-                let num_matching = self
-                    .input
-                    .chars()
-                    .zip(
-                        self.text_controller_data.lyrics[self.text_controller_data.current_line]
-                            .chars(),
-                    )
-                    .take_while(|(a, b)| a == b)
-                    .count();
-                if num_matching
-                    >= self.text_controller_data.lyrics[self.text_controller_data.current_line]
-                        .chars()
-                        .count()
+                if self.replaying.is_some() || self.results.is_some() {
+                    return Task::none();
+                }
+                if let Some(window) = self.text_controller_data.current_window()
+                    && !value.is_empty()
                 {
-                    self.score += 1;
-                    self.input = "".into();
-                    if !self.text_controller_data.count_up() {
-                        return Task::done(Message::NextLyricBatch);
+                    match self.song_time() {
+                        // the first keystroke starts the clock at the current line
+                        None => {
+                            self.song_clock =
+                                Some(SongClock::starting_at(window.start, Instant::now()))
+                        }
+                        Some(t) if t < window.start => {
+                            self.session.clear_input();
+                            return Task::none();
+                        }
+                        Some(_) => {}
                     }
                 }
-                if let Some((symbol, v)) = self.char_bonus.clone() {
-                    if v.contains(&self.input.chars().skip(num_matching).collect()) {
-                        return Task::done(Message::InputChanged(
-                            self.input.chars().take(num_matching).collect::<String>()
-                                + symbol.as_str(),
-                        ));
+                self.now = Instant::now();
+                let line_index = self.text_controller_data.current_line;
+                if self.pace.is_none()
+                    && let Some(wpm) = self.target_wpm
+                {
+                    self.pace = Some(Pace::new(wpm, line_index, self.now));
+                }
+                let result = self.session.handle_input(value.clone(), self.now);
+                let position = match &result {
+                    InputResult::LineCompleted(line) => line.text.chars().count(),
+                    InputResult::Typing => self.session.position(),
+                };
+                self.recorder.record(line_index, &value, position, self.now);
+                let synced = self
+                    .text_controller_data
+                    .current_window()
+                    .zip(self.song_time());
+                // typing along to a song scores each word on its timing instead of the line
+                if let Some((window, t)) = synced
+                    && let Some(target) = self.text_controller_data.lyrics.get(line_index)
+                {
+                    self.score += self
+                        .rhythm
+                        .judge_words(target, window, position, t, self.now);
+                }
+                if let InputResult::LineCompleted(line) = result {
+                    match synced {
+                        Some((window, t)) => self.last_grade = Some(SyncGrade::grade(window, t)),
+                        None => self.score += line.points(),
                     }
+                    if let Some(reason) = self.run_end() {
+                        return self.end_run(reason);
+                    }
+                    if !self.text_controller_data.count_up() {
+                        return Task::done(Message::NextLyricBatch);
+                    }
+                    self.start_current_line();
+                } else if let Some(reason) = self.run_end() {
+                    return self.end_run(reason);
                 }
-
                 return Task::done(Message::CheckForeignChars);
             }
             Message::InputSubmitted => {
-                let mut v = self.input.clone();
+                let mut v = self.session.input().to_string();
                 v.push(' ');
                 return Task::done(Message::InputChanged(v));
             }
@@ -273,7 +586,7 @@ impl TypingGame {
                                 .await
                                 .map_err(|e| e.to_string())
                         },
-                        |out| Message::APIResult("play".into(), out),
+                        Message::Played,
                     );
                 }
             }
@@ -313,50 +626,201 @@ impl TypingGame {
             }
             Message::APIResult(kind, result) => match result {
                 Err(e) => println!("API Error: {}", e),
-                Ok(_) => match kind.as_str() {
-                    "play" => self.spotify_data.is_playing = true,
-                    "pause" => self.spotify_data.is_playing = false,
-                    _ => {}
-                },
+                Ok(_) => {
+                    if kind == "pause" {
+                        self.spotify_data.is_playing = false;
+                        if let Some(clock) = &mut self.song_clock {
+                            clock.pause(Instant::now());
+                        }
+                    }
+                }
+            },
+            Message::Played(result) => match result {
+                Err(e) => println!("API Error: {}", e),
+                Ok(new_song) => {
+                    self.spotify_data.is_playing = true;
+                    if self.text_controller_data.windows.is_empty() {
+                        return Task::none();
+                    }
+                    if new_song {
+                        // a new song starts from the top, so synced text starts with it
+                        self.song_clock =
+                            Some(SongClock::starting_at(Duration::ZERO, Instant::now()));
+                    } else if let Some(clock) = &mut self.song_clock {
+                        // resuming carries on from where the song was paused
+                        clock.resume(Instant::now());
+                    }
+                }
+            },
+            Message::PollPlayback => {
+                if let SpotifyControllerHandle::Ready(controller) = &self.spotify_controller_handle
+                {
+                    let controller = controller.clone();
+                    return Task::perform(
+                        async move {
+                            controller
+                                .lock()
+                                .await
+                                .playback_state()
+                                .await
+                                .map_err(|e| e.to_string())
+                        },
+                        Message::PlaybackPolled,
+                    );
+                }
+            }
+            Message::PlaybackPolled(result) => match result {
+                Err(e) => log::warn!("could not poll playback: {}", e),
+                Ok(None) => self.spotify_data.is_playing = false,
+                Ok(Some(state)) => {
+                    self.spotify_data.is_playing = state.is_playing;
+                    // synced text follows the player, as long as it's playing the song
+                    // the text is for
+                    if !self.text_controller_data.windows.is_empty()
+                        && state.track_id.is_some()
+                        && state.track_id == self.session_song_id
+                    {
+                        self.song_clock = Some(SongClock::synced(
+                            state.progress,
+                            state.is_playing,
+                            state.at,
+                        ));
+                    }
+                }
             },
             Message::InitializeComplete(result) => match result {
                 Ok(obj) => match obj {
                     InitializerObject::Spotify(sp) => {
                         self.spotify_controller_handle = SpotifyControllerHandle::Ready(sp);
-                        return Task::perform(
-                            async {
-                                let text_controller = TextController::init().await;
-                                Ok(InitializerObject::Text(Arc::new(Mutex::new(
-                                    text_controller,
-                                ))))
-                            },
-                            Message::InitializeComplete,
-                        );
+                        return self.init_text_controller();
                     }
                     InitializerObject::Text(tx) => {
                         self.text_controller_handle = TextControllerHandle::Ready(tx);
-                        return Task::perform(
-                            async {
-                                let char_controller = CharController::init(vec![
-                                    include_str!("../assets/kana_maps/special.json"),
-                                    include_str!("../assets/kana_maps/hiragana.json"),
-                                    include_str!("../assets/kana_maps/katakana.json"),
-                                    include_str!("../assets/kana_maps/kanji-joyo.json"),
-                                    include_str!("../assets/kana_maps/kanji-jinmeiyo.json"),
-                                ])
-                                .await;
-                                Ok(InitializerObject::Char(char_controller))
-                            },
-                            Message::InitializeComplete,
-                        );
+                        return Task::batch([
+                            Task::done(Message::RefreshCache),
+                            Task::perform(
+                                async {
+                                    let user_words = char_controller::dictionary_from_env();
+                                    let mut dictionaries =
+                                        vec![include_str!("../assets/dictionaries/common.edict")];
+                                    dictionaries.extend(user_words.as_deref());
+                                    let char_controller = CharController::init(
+                                        vec![
+                                            include_str!("../assets/kana_maps/special.json"),
+                                            include_str!("../assets/kana_maps/hiragana.json"),
+                                            include_str!("../assets/kana_maps/katakana.json"),
+                                            include_str!("../assets/kana_maps/kanji-joyo.json"),
+                                            include_str!("../assets/kana_maps/kanji-jinmeiyo.json"),
+                                        ],
+                                        dictionaries,
+                                    )
+                                    .await;
+                                    Ok(InitializerObject::Char(char_controller))
+                                },
+                                Message::InitializeComplete,
+                            ),
+                        ]);
                     }
                     InitializerObject::Char(cx) => {
                         self.char_controller_handle = CharControllerHandle::Ready(cx);
+                        self.update_readings();
                     }
                 },
-                Err(_) => todo!(),
+                // only Spotify can fail, and everything else works offline without it
+                Err(e) => {
+                    log::error!("could not connect to Spotify: {}", e);
+                    return self.init_text_controller();
+                }
             },
-            Message::Tick(_instant) => (),
+            Message::RefreshCache => {
+                if let TextControllerHandle::Ready(controller) = &self.text_controller_handle {
+                    let controller = controller.clone();
+                    return Task::perform(
+                        async move { controller.lock().await.cache_listing() },
+                        Message::UpdateCache,
+                    );
+                }
+            }
+            Message::UpdateCache(listing) => self.cached_songs = listing,
+            Message::PracticeCached(cached) => {
+                self.spotify_data.current_song = Some(Song {
+                    name: cached.track,
+                    id: cached.song_id.unwrap_or_default(),
+                    artist: cached.artist,
+                    album: Some(cached.album),
+                    duration_ms: Some((cached.duration * 1000.0) as i64),
+                });
+                if !matches!(
+                    self.text_controller_data.text_type,
+                    TextType::Lrclib | TextType::SyncedLrclib
+                ) {
+                    self.text_controller_data.text_type = TextType::Lrclib;
+                }
+                return Task::done(Message::LoadNewText);
+            }
+            Message::RemoveCached(key) => {
+                if let TextControllerHandle::Ready(controller) = &self.text_controller_handle {
+                    let controller = controller.clone();
+                    return Task::perform(
+                        async move {
+                            let mut text_controller = controller.lock().await;
+                            text_controller.remove_cached(&key);
+                            text_controller.cache_listing()
+                        },
+                        Message::UpdateCache,
+                    );
+                }
+            }
+            Message::ClearCache => {
+                if let TextControllerHandle::Ready(controller) = &self.text_controller_handle {
+                    let controller = controller.clone();
+                    return Task::perform(
+                        async move {
+                            let mut text_controller = controller.lock().await;
+                            text_controller.clear_cache();
+                            text_controller.cache_listing()
+                        },
+                        Message::UpdateCache,
+                    );
+                }
+            }
+            Message::CycleCacheLimit => {
+                let next_limit = CACHE_LIMITS
+                    .iter()
+                    .copied()
+                    .find(|v| *v > self.cached_songs.max_bytes)
+                    .unwrap_or(CACHE_LIMITS[0]);
+                if let TextControllerHandle::Ready(controller) = &self.text_controller_handle {
+                    let controller = controller.clone();
+                    return Task::perform(
+                        async move {
+                            let mut text_controller = controller.lock().await;
+                            text_controller.set_cache_limit(next_limit);
+                            text_controller.cache_listing()
+                        },
+                        Message::UpdateCache,
+                    );
+                }
+            }
+            Message::Tick(instant) => {
+                if self.replaying.is_some() {
+                    self.advance_replay(instant);
+                    return Task::none();
+                }
+                self.now = instant;
+                if self.results.is_none()
+                    && let Some(reason) = self.run_end()
+                {
+                    return self.end_run(reason);
+                }
+                if let Some(window) = self.text_controller_data.current_window()
+                    && let Some(t) = self.song_time()
+                    && t > window.end + lrc::MISS_AFTER
+                {
+                    self.last_grade = Some(SyncGrade::Missed);
+                    return Task::done(Message::SkipLine);
+                }
+            }
             Message::DevicesResult(items) => match items {
                 Ok(items) => self.spotify_data.devices_list = items,
                 Err(e) => log::error!("could not fetch devices {}", e),
@@ -388,92 +852,279 @@ impl TypingGame {
                 }
             }
             Message::SetLRCLIBText => {
-                self.text_controller_data.text_type = TextType::LRCLIB;
+                self.text_controller_data.text_type = TextType::Lrclib;
                 return Task::done(Message::LoadNewText);
             }
+            Message::SetSyncedLRCLIBText => {
+                self.text_controller_data.text_type = TextType::SyncedLrclib;
+                return Task::done(Message::LoadNewText);
+            }
+            Message::LyricsPathChanged(path) => self.lyrics_path = path,
+            Message::SetLocalFileText => {
+                let path = self.lyrics_path.trim();
+                if !path.is_empty() {
+                    self.text_controller_data.text_type = TextType::LocalFile(path.into());
+                    return Task::done(Message::LoadNewText);
+                }
+            }
             Message::SetSourceFileText => {
-                self.text_controller_data.text_type = TextType::ThisProject;
+                self.text_controller_data.text_type =
+                    TextType::SourceTree(self.source_tree.clone());
+                return Task::done(Message::LoadNewText);
+            }
+            Message::SetLessonText => {
+                self.text_controller_data.text_type = TextType::Lesson(Lesson::from_stats(
+                    self.session.key_stats(),
+                    self.lesson_words,
+                ));
                 return Task::done(Message::LoadNewText);
             }
+            Message::ToggleLessonWords => {
+                self.lesson_words = match self.lesson_words {
+                    LessonWords::Pseudo => LessonWords::Real,
+                    LessonWords::Real => LessonWords::Pseudo,
+                }
+            }
+            Message::SourceRootChanged(root) => self.source_tree.root = root.into(),
+            Message::SourceExtensionsChanged(extensions) => {
+                self.source_tree.extensions = extensions
+            }
+            Message::SourceGlobChanged(glob) => self.source_tree.glob = glob,
+            Message::ToggleSourceOrder => {
+                self.source_tree.order = match self.source_tree.order {
+                    FileOrder::Random => FileOrder::Sequential,
+                    FileOrder::Sequential => FileOrder::Random,
+                }
+            }
+            Message::CycleIndentStyle => {
+                self.source_tree.code.indent_style = match self.source_tree.code.indent_style {
+                    IndentStyle::Spaces => IndentStyle::Tabs,
+                    IndentStyle::Tabs => IndentStyle::Keep,
+                    IndentStyle::Keep => IndentStyle::Flat,
+                    IndentStyle::Flat => IndentStyle::Spaces,
+                }
+            }
+            Message::CycleTabWidth => {
+                self.source_tree.code.tab_width = match self.source_tree.code.tab_width {
+                    2 => 4,
+                    4 => 8,
+                    _ => 2,
+                }
+            }
+            Message::ToggleIndentMode => {
+                self.source_tree.code.indent_mode = match self.source_tree.code.indent_mode {
+                    IndentMode::Skip => IndentMode::Require,
+                    IndentMode::Require => IndentMode::Skip,
+                };
+                self.session
+                    .set_indent_mode(self.source_tree.code.indent_mode);
+            }
+            Message::ToggleRomajiSystem(system) => {
+                self.romaji.toggle(system);
+                self.update_readings();
+                return Task::done(Message::CheckForeignChars);
+            }
+            Message::CycleRomajiHint => {
+                self.romaji.cycle_hint();
+                self.update_readings();
+                return Task::done(Message::CheckForeignChars);
+            }
+            Message::CycleAnnotation => {
+                self.annotation = next_annotation(self.annotation);
+                self.line_annotations.clear();
+                self.update_readings();
+            }
+            Message::CycleLineAnnotation(line) => {
+                self.line_annotations
+                    .insert(line, next_annotation(self.line_annotation(line)));
+                self.update_readings();
+            }
+            Message::ToggleKeyStats => self.show_key_stats = !self.show_key_stats,
+            Message::CycleStrictness => {
+                self.session
+                    .set_strictness(match self.session.strictness() {
+                        Strictness::StopOnError => Strictness::ContinueThroughErrors,
+                        Strictness::ContinueThroughErrors => Strictness::NoBackspace,
+                        Strictness::NoBackspace => Strictness::StopOnError,
+                    })
+            }
             Message::NextLyricBatch => {
                 if let TextControllerHandle::Ready(controller) = &self.text_controller_handle {
                     let controller = controller.clone();
                     let data = self.text_controller_data.clone();
+                    // lessons adapt to the latest key stats a page at a time
+                    let lesson = match &data.text_type {
+                        TextType::Lesson(lesson) => {
+                            Some(Lesson::from_stats(self.session.key_stats(), lesson.words()))
+                        }
+                        _ => None,
+                    };
                     return Task::perform(
                         async move {
                             let mut text_controller = controller.lock().await;
-                            let lyrics = match text_controller
+                            if let Some(lesson) = lesson {
+                                text_controller.adapt_lesson(lesson);
+                            }
+                            let windows = text_controller
+                                .fetch_windows(data.next_fetch_line as usize)
+                                .await;
+                            let lyrics = text_controller
                                 .fetch_lyrics(data.next_fetch_line as usize)
                                 .await
-                            {
-                                Some(v) => {
-                                    if v.len() > 0 {
-                                        v
-                                    } else {
-                                        vec![
-                                            "No more lyrics".into(),
-                                            "Please load some more".into(),
-                                            "No more lyrics".into(),
-                                            "Please load some more".into(),
-                                            "No more lyrics".into(),
-                                            "Please load some more".into(),
-                                        ]
-                                    }
-                                }
-                                None => vec![
-                                    "No more lyrics".into(),
-                                    "Please load some more".into(),
-                                    "No more lyrics".into(),
-                                    "Please load some more".into(),
-                                    "No more lyrics".into(),
-                                    "Please load some more".into(),
-                                ],
-                            };
-                            TextControllerData {
+                                .filter(|v| !v.is_empty())?;
+                            Some(TextControllerData {
                                 text_type: data.text_type,
+                                title: text_controller.loaded_title(),
                                 lyrics,
+                                windows,
                                 current_line: 0,
                                 next_fetch_line: data.next_fetch_line
                                     + text_controller::NUM_LINES as i32,
-                            }
+                            })
+                        },
+                        |data| match data {
+                            Some(data) => Message::UpdateText(data),
+                            None => Message::TextFinished,
                         },
-                        Message::UpdateText,
                     );
                 }
             }
             Message::SkipLine => {
-                self.input = "".into();
+                if self.replaying.is_some() {
+                    return Task::none();
+                }
+                if let Some(window) = self.text_controller_data.current_window()
+                    && self.song_time().is_some()
+                    && let Some(line) = self
+                        .text_controller_data
+                        .lyrics
+                        .get(self.text_controller_data.current_line)
+                {
+                    self.rhythm.miss_rest(line, window, Instant::now());
+                }
+                self.session.clear_input();
                 if !self.text_controller_data.count_up() {
                     return Task::done(Message::NextLyricBatch);
                 }
+                self.start_current_line();
                 return Task::done(Message::CheckForeignChars);
             }
             Message::LoadNewText => {
+                self.stop_replay();
+                self.results = None;
                 self.text_controller_data.current_line = 0;
                 self.text_controller_data.next_fetch_line = 0;
+                self.song_clock = None;
+                self.last_grade = None;
+                self.rhythm.reset();
+                let save_session = self.finish_session();
+                self.ghost = None;
+                self.pace = None;
+                self.session_source = self.text_controller_data.text_type.label();
+                self.session_song_id = match self.text_controller_data.text_type {
+                    TextType::Lrclib | TextType::SyncedLrclib => self
+                        .spotify_data
+                        .current_song
+                        .as_ref()
+                        .map(|v| v.id.clone())
+                        .filter(|v| !v.is_empty()),
+                    _ => None,
+                };
                 if let TextControllerHandle::Ready(controller) = &self.text_controller_handle {
                     let controller = controller.clone();
                     let data = self.text_controller_data.clone();
                     let current_song = self.spotify_data.current_song.clone();
-                    return Task::perform(
+                    let load_text = Task::perform(
                         async move {
                             let mut text_controller = controller.lock().await;
                             let settings = match data.text_type {
-                                TextType::LRCLIB => match current_song {
-                                    Some(song) => Some(song.name + " " + song.artist.as_str()),
-                                    None => None,
-                                },
+                                TextType::Lrclib | TextType::SyncedLrclib => {
+                                    current_song.map(LyricsQuery::from)
+                                }
                                 // TextType::Github => todo!(),
-                                TextType::ThisProject => None,
+                                TextType::LocalFile(_)
+                                | TextType::SourceTree(_)
+                                | TextType::Lesson(_) => None,
                             };
                             text_controller.load_lyrics(data.text_type, settings).await;
+                            text_controller.lyrics_candidates()
+                        },
+                        Message::UpdateLyricsCandidates,
+                    );
+                    return Task::batch([save_session, load_text]);
+                }
+                return save_session;
+            }
+            Message::UpdateLyricsCandidates(candidates) => {
+                self.lyrics_candidates = candidates;
+                return Task::batch([
+                    Task::done(Message::NextLyricBatch),
+                    Task::done(Message::RefreshCache),
+                ]);
+            }
+            Message::SearchLyricsProviders => {
+                if let TextControllerHandle::Ready(controller) = &self.text_controller_handle {
+                    let controller = controller.clone();
+                    let synced =
+                        matches!(self.text_controller_data.text_type, TextType::SyncedLrclib);
+                    return Task::perform(
+                        async move {
+                            let mut text_controller = controller.lock().await;
+                            text_controller.search_providers(synced).await;
+                            text_controller.lyrics_candidates()
+                        },
+                        Message::ShowLyricsCandidates,
+                    );
+                }
+            }
+            Message::ShowLyricsCandidates(candidates) => self.lyrics_candidates = candidates,
+            Message::ChooseLyricsCandidate(index) => {
+                self.stop_replay();
+                self.results = None;
+                self.text_controller_data.current_line = 0;
+                self.text_controller_data.next_fetch_line = 0;
+                self.song_clock = None;
+                self.last_grade = None;
+                self.rhythm.reset();
+                // the other lyrics are a new text, so what was typed so far is its own session
+                let save_session = self.finish_session();
+                self.ghost = None;
+                self.pace = None;
+                if let TextControllerHandle::Ready(controller) = &self.text_controller_handle {
+                    let controller = controller.clone();
+                    let synced =
+                        matches!(self.text_controller_data.text_type, TextType::SyncedLrclib);
+                    let select = Task::perform(
+                        async move {
+                            controller.lock().await.select_candidate(index, synced);
                         },
                         |_| Message::NextLyricBatch,
                     );
+                    return Task::batch([save_session, select]);
                 }
+                return save_session;
             }
             Message::UpdateText(data) => {
+                self.recorder.add_page(data.lyrics.clone());
+                self.pace = None;
+                if let Some(replaying) = &mut self.replaying {
+                    // picked up again when the replay ends
+                    let line = data.lyrics.get(data.current_line).cloned();
+                    replaying.saved_session.start_line(line.unwrap_or_default());
+                    replaying.saved_text = data;
+                    return Task::none();
+                }
                 self.text_controller_data = data;
+                self.line_annotations.clear();
+                self.update_readings();
+                self.start_current_line();
+                // the race starts with the first page of the text
+                if self.recorder.page() == 0 {
+                    return Task::batch([
+                        Task::done(Message::CheckForeignChars),
+                        self.find_ghost(),
+                    ]);
+                }
                 return Task::done(Message::CheckForeignChars);
             }
             Message::QueryChanged(query) => self.query = query,
@@ -488,86 +1139,249 @@ impl TypingGame {
                     );
                 }
             }
-            Message::UpdateSongs(result) => match result {
-                Some(new_songs) => self.spotify_data.songs_list = new_songs,
-                None => {}
-            },
+            Message::UpdateSongs(result) => {
+                if let Some(new_songs) = result {
+                    self.spotify_data.songs_list = new_songs
+                }
+            }
             Message::HideDevices => self.spotify_data.devices_list = vec![],
             Message::CheckForeignChars => {
                 if let CharControllerHandle::Ready(cc) = &self.char_controller_handle {
-                    let line = self.text_controller_data.lyrics
-                        [self.text_controller_data.current_line]
-                        .clone();
-                    let num_matching = self
-                        .input
-                        .chars()
-                        .zip(line.chars())
-                        .take_while(|(a, b)| a == b)
-                        .count();
-                    let out = if num_matching < line.chars().count() - 1 {
-                        let special_char: String =
-                            line.chars().skip(num_matching).take(2).collect();
-                        match cc.check_special_char(&special_char) {
-                            Some(v) => Some((special_char, v.clone())),
-                            None => None,
-                        }
-                    } else {
-                        None
-                    };
-                    self.char_bonus = if out == None {
-                        let symbol: String = line.chars().nth(num_matching).unwrap_or(' ').into();
-                        match cc.get_play_char(symbol.as_str()) {
-                            Some(v) => Some((symbol, v.clone())),
-                            None => None,
-                        }
-                    } else {
-                        out
-                    }
+                    self.session.check_foreign_chars(cc, &self.romaji);
                 }
             }
+            Message::KeyStatsLoaded(keys) => self.session.set_key_stats(keys),
+            Message::HistoryLoaded(history) | Message::UpdateHistory(history) => {
+                self.history = history
+            }
+            Message::ToggleHistoryPeriod => {
+                self.history_period = match self.history_period {
+                    TrendPeriod::Day => TrendPeriod::Week,
+                    TrendPeriod::Week => TrendPeriod::Day,
+                }
+            }
+            Message::CloseRequested => {
+                self.stop_replay();
+                return self.finish_session().chain(iced::exit());
+            }
+            Message::ReplayPathChanged(path) => self.replay_path = path,
+            Message::WatchReplay => {
+                let path = PathBuf::from(self.replay_path.trim());
+                return Task::perform(
+                    async move { Replay::load(&path).map_err(|e| e.to_string()) },
+                    Message::StartReplay,
+                );
+            }
+            Message::WatchLastReplay => {
+                if let Some(path) = self.last_replay.clone() {
+                    return Task::perform(
+                        async move { Replay::load(&path).map_err(|e| e.to_string()) },
+                        Message::StartReplay,
+                    );
+                }
+            }
+            Message::StartReplay(result) => match result {
+                Ok(replay) => self.start_replay(replay),
+                Err(e) => log::error!("could not load replay: {}", e),
+            },
+            Message::CycleReplaySpeed => {
+                self.replay_speed = match self.replay_speed {
+                    v if v < 2.0 => 2.0,
+                    v if v < 4.0 => 4.0,
+                    _ => 1.0,
+                };
+                if let Some(replaying) = &mut self.replaying {
+                    replaying
+                        .playback
+                        .set_speed(self.replay_speed, Instant::now());
+                }
+            }
+            Message::StopReplay => self.stop_replay(),
+            Message::ReplaySaved(path) => {
+                if path.is_some() {
+                    self.last_replay = path;
+                }
+            }
+            Message::ToggleGhost => {
+                self.race_ghost = !self.race_ghost;
+                self.ghost = None;
+                return self.find_ghost();
+            }
+            Message::UpdateGhost(ghost) => self.ghost = ghost,
+            Message::CycleSessionMode => {
+                self.session_mode = self.session_mode.next();
+                // a new mode starts on fresh text, unless the results are still up
+                if self.results.is_none() {
+                    return Task::done(Message::LoadNewText);
+                }
+            }
+            Message::TextFinished => {
+                if self.session.completed_stats().lines == 0 {
+                    self.text_controller_data.lyrics = vec![NOTHING_TO_TYPE.into()];
+                    self.text_controller_data.windows = vec![];
+                    self.text_controller_data.current_line = 0;
+                    self.start_current_line();
+                    return Task::none();
+                }
+                return match self.session_mode.text_end() {
+                    Some(reason) => self.end_run(reason),
+                    None => Task::done(Message::LoadNewText),
+                };
+            }
+            Message::PaceChanged(value) => {
+                self.pace_input = value.chars().filter(|v| v.is_ascii_digit()).collect();
+                self.target_wpm = self.pace_input.parse::<f32>().ok().filter(|v| *v > 0.0);
+                self.pace = None;
+            }
         }
         Task::none()
     }
 
     fn view(&self) -> Element<'_, Message> {
-        if self.text_controller_data.lyrics.len() > 0 {
-            let num_matching = self
-                .input
-                .chars()
-                .zip(
-                    self.text_controller_data.lyrics[self.text_controller_data.current_line]
-                        .chars(),
-                )
-                .take_while(|(a, b)| a == b)
-                .count();
+        if let Some(results) = &self.results {
+            return self.results_view(results).into();
+        }
+        if !self.text_controller_data.lyrics.is_empty() {
             let pre: Column<_> = self.text_controller_data.lyrics
                 [0..self.text_controller_data.current_line]
                 .iter()
-                .fold(Column::new(), |col, v| {
-                    col.push(text(v).style(|_| text::Style {
-                        color: Some(COMPLETED_COLOR),
-                    }))
+                .enumerate()
+                .fold(Column::new(), |col, (i, v)| {
+                    col.push(self.annotated_line(
+                        i,
+                        marked_row(vec![(COMPLETED_COLOR, v.clone())], &self.markers_on(i)),
+                    ))
                 });
+            let song_time = self.song_time();
             let post: Column<_> = self.text_controller_data.lyrics
                 [self.text_controller_data.current_line + 1..]
                 .iter()
-                .fold(Column::new(), |col, v| {
-                    col.push(text(v).style(|_| text::Style {
-                        color: Some(UPCOMING_COLOR),
-                    }))
+                .enumerate()
+                // synced lines only show up once the song reaches them
+                .filter(|(i, _)| {
+                    match (
+                        self.text_controller_data
+                            .windows
+                            .get(self.text_controller_data.current_line + 1 + i),
+                        song_time,
+                    ) {
+                        (Some(window), Some(t)) => window.start <= t,
+                        _ => true,
+                    }
+                })
+                .fold(Column::new(), |col, (i, v)| {
+                    let line = self.text_controller_data.current_line + 1 + i;
+                    col.push(self.annotated_line(
+                        line,
+                        marked_row(vec![(UPCOMING_COLOR, v.clone())], &self.markers_on(line)),
+                    ))
                 });
-            let target = &self.text_controller_data.lyrics[self.text_controller_data.current_line];
-            let matching_substr: String = target.chars().take(num_matching).collect();
-            let remaining_substr: String = target.chars().skip(num_matching).collect();
+            let wait_time = match (self.text_controller_data.current_window(), song_time) {
+                (Some(window), Some(t)) if t < window.start => Some(window.start - t),
+                _ => None,
+            };
+            let remaining_color = if wait_time.is_some() {
+                WAITING_COLOR
+            } else {
+                PREPARE_COLOR
+            };
+            let stats = self.session.stats(self.now);
+            let metrics = self.session.metrics(self.now);
             let mut info_row: Row<_> = row![
                 text(format!("Score: {}", self.score)),
+                Space::with_width(40),
+                text(format!(
+                    "{:.0} WPM ({:.0} raw), {:.0} CPM, {:.0}% accuracy, {} errors over {} lines",
+                    metrics.wpm,
+                    metrics.raw_wpm,
+                    metrics.cpm,
+                    metrics.accuracy * 100.0,
+                    stats.errors,
+                    stats.lines
+                )),
                 Space::with_width(40)
             ];
-            match &self.char_bonus {
-                Some((symbol, v)) => {
-                    info_row = info_row.push(text(format!("{} -> {:?}", symbol, v)));
+            let line_metrics = self.session.line_metrics(self.now);
+            if line_metrics.wpm > 0.0 {
+                info_row = info_row.push(text(format!(
+                    "This line {:.0} WPM, {:.0}%, {} errors",
+                    line_metrics.wpm,
+                    line_metrics.accuracy * 100.0,
+                    self.session.line_errors()
+                )));
+                info_row = info_row.push(Space::with_width(40));
+            } else if let Some(line) = self.session.last_line() {
+                let last_metrics = line.metrics();
+                info_row = info_row.push(text(format!(
+                    "Last line {:.0} WPM, {:.0}%, {} errors",
+                    last_metrics.wpm,
+                    last_metrics.accuracy * 100.0,
+                    line.errors
+                )));
+                info_row = info_row.push(Space::with_width(40));
+            }
+            match self.session_mode {
+                SessionMode::Timed(limit) => {
+                    let elapsed = self.recorder.elapsed(self.now).unwrap_or_default();
+                    info_row = info_row.push(text(format!(
+                        "{:.0}s left",
+                        limit.saturating_sub(elapsed).as_secs_f32().ceil()
+                    )));
+                    info_row = info_row.push(Space::with_width(40));
+                }
+                SessionMode::Words(count) => {
+                    info_row = info_row.push(text(format!(
+                        "{}/{} words",
+                        self.session.words_typed(),
+                        count
+                    )));
+                    info_row = info_row.push(Space::with_width(40));
                 }
-                None => (),
+                SessionMode::Endless | SessionMode::FinishText => {}
+            }
+            if let Some(delta) = self.ghost_delta() {
+                info_row = info_row.push(text(delta));
+                info_row = info_row.push(Space::with_width(40));
+            }
+            if let Some(wait) = wait_time {
+                info_row = info_row.push(text(format!("Next line in {:.1}s", wait.as_secs_f32())));
+                info_row = info_row.push(Space::with_width(40));
+            }
+            if let Some(grade) = &self.last_grade {
+                info_row = info_row.push(text(grade.to_string()));
+                info_row = info_row.push(Space::with_width(40));
+            }
+            if self.text_controller_data.current_window().is_some() && song_time.is_some() {
+                info_row = info_row.push(text(format!(
+                    "Combo {} (x{}), best {}",
+                    self.rhythm.combo(),
+                    self.rhythm.multiplier(),
+                    self.rhythm.max_combo()
+                )));
+                info_row = info_row.push(Space::with_width(40));
+            }
+            if let Some(judgment) = self.rhythm.popup(self.now) {
+                let color = match judgment {
+                    Judgment::Perfect => PERFECT_COLOR,
+                    Judgment::Great => MATCHING_COLOR,
+                    Judgment::Good => PREPARE_COLOR,
+                    Judgment::Miss => ERROR_COLOR,
+                };
+                info_row = info_row.push(
+                    text(judgment.to_string())
+                        .size(24)
+                        .style(move |_| text::Style { color: Some(color) }),
+                );
+                info_row = info_row.push(Space::with_width(40));
+            }
+            if let Some((symbol, spellings)) = self.session.char_bonus() {
+                // the hint first, then a couple of the other ways to type it
+                let shown: Vec<&str> = spellings
+                    .iter()
+                    .take(SHOWN_SPELLINGS)
+                    .map(|v| v.as_str())
+                    .collect();
+                info_row = info_row.push(text(format!("{}  {}", symbol, shown.join(" / "))));
             }
             let mut songs_ui = Column::new().padding(10).spacing(10);
             for song in &self.spotify_data.songs_list {
@@ -598,27 +1412,21 @@ impl TypingGame {
                             button("Play").on_press(Message::SpotifyPlay),
                             button("Pause").on_press(Message::SpotifyPause)
                         ],
-                        text("Text Style"),
-                        row![
-                            button("LRCLIB").on_press(Message::SetLRCLIBText),
-                            // button("Github").on_press(Message::SetGithubText),
-                            button("Source File").on_press(Message::SetSourceFileText),
-                        ],
+                        self.text_style_view(),
+                        text(self.text_controller_data.title.clone().unwrap_or_default()),
                         pre,
-                        row![
-                            text(matching_substr).style(|_| text::Style {
-                                color: Some(MATCHING_COLOR)
-                            }),
-                            text(remaining_substr).style(|_| text::Style {
-                                color: Some(PREPARE_COLOR)
-                            }),
-                        ],
+                        self.annotated_line(
+                            self.text_controller_data.current_line,
+                            self.current_line_view(remaining_color)
+                        ),
                         post,
-                        text_input("Start typing...", &self.input)
+                        text_input("Start typing...", self.session.input())
                             .on_input(Message::InputChanged)
                             .on_submit(Message::InputSubmitted),
                         info_row,
-                        row![button("Skip Line").on_press(Message::SkipLine)]
+                        row![button("Skip Line").on_press(Message::SkipLine)],
+                        self.replay_view(),
+                        self.key_stats_view(),
                     ],
                     column![
                         text("Songs"),
@@ -630,35 +1438,33 @@ impl TypingGame {
                             button("Search").on_press(Message::QuerySubmitted)
                         ],
                         songs_ui,
+                        self.lyrics_candidates_view(),
+                        self.cache_view(),
+                        self.history_view(),
                     ]
                 ]
                 .into()
             } else {
                 row![
                     column![
-                        text("Text Style"),
-                        row![
-                            button("LRCLIB").on_press(Message::SetLRCLIBText),
-                            // button("Github").on_press(Message::SetGithubText),
-                            button("Source File").on_press(Message::SetSourceFileText),
-                        ],
+                        self.text_style_view(),
                         row![
                             text("Loading Spotify..."),
                             button("Retry").on_press(Message::InitializeStart)
                         ],
                         text(""),
+                        text(self.text_controller_data.title.clone().unwrap_or_default()),
                         pre,
-                        row![
-                            text(matching_substr).style(|_| text::Style {
-                                color: Some(MATCHING_COLOR)
-                            }),
-                            text(remaining_substr).style(|_| text::Style {
-                                color: Some(PREPARE_COLOR)
-                            }),
-                        ],
+                        self.annotated_line(
+                            self.text_controller_data.current_line,
+                            self.current_line_view(remaining_color)
+                        ),
                         post,
-                        text_input("Start typing...", &self.input).on_input(Message::InputChanged),
-                        text(format!("Score: {}", self.score)),
+                        text_input("Start typing...", self.session.input())
+                            .on_input(Message::InputChanged),
+                        info_row,
+                        self.replay_view(),
+                        self.key_stats_view(),
                     ],
                     column![
                         // row![
@@ -669,6 +1475,9 @@ impl TypingGame {
                         //     button("Search").on_press(Message::QuerySubmitted)
                         // ],
                         songs_ui,
+                        self.lyrics_candidates_view(),
+                        self.cache_view(),
+                        self.history_view(),
                     ]
                 ]
                 .into()
@@ -678,8 +1487,753 @@ impl TypingGame {
         }
     }
 
+    /// A keyboard colored by how much trouble each key gives, with the worst keys and pairs.
+    fn key_stats_view(&self) -> Column<'_, Message> {
+        let toggle_label = if self.show_key_stats {
+            "Hide Key Stats"
+        } else {
+            "Show Key Stats"
+        };
+        let toggle = button(toggle_label).on_press(Message::ToggleKeyStats);
+        if !self.show_key_stats {
+            return column![toggle];
+        }
+        let stats = self.session.key_stats();
+        let key_view = |key: char, label: String, width: f32| {
+            let color = match stats.difficulty(key) {
+                Some(v) => iced::Color {
+                    r: EASY_KEY_COLOR.r + (HARD_KEY_COLOR.r - EASY_KEY_COLOR.r) * v,
+                    g: EASY_KEY_COLOR.g + (HARD_KEY_COLOR.g - EASY_KEY_COLOR.g) * v,
+                    b: EASY_KEY_COLOR.b + (HARD_KEY_COLOR.b - EASY_KEY_COLOR.b) * v,
+                    a: 1.0,
+                },
+                None => UNTYPED_KEY_COLOR,
+            };
+            container(text(label))
+                .width(Length::Fixed(width))
+                .padding(6)
+                .style(move |_| container::Style {
+                    background: Some(color.into()),
+                    ..Default::default()
+                })
+        };
+        let mut keyboard = Column::new().spacing(4);
+        for (i, keys) in KEYBOARD_ROWS.iter().enumerate() {
+            let mut keys_row = Row::new()
+                .spacing(4)
+                .push(Space::with_width(i as f32 * 12.0));
+            for key in keys.chars() {
+                keys_row = keys_row.push(key_view(key, key.to_string(), 32.0));
+            }
+            keyboard = keyboard.push(keys_row);
+        }
+        keyboard = keyboard.push(row![
+            Space::with_width(96),
+            key_view(' ', "space".into(), 220.0)
+        ]);
+
+        let slowest = stats
+            .slowest_bigrams(5)
+            .iter()
+            .map(|(pair, stat)| {
+                format!(
+                    "{} {}ms",
+                    pair.replace(' ', "_"),
+                    stat.mean_latency().unwrap_or_default().as_millis()
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let most_missed = stats
+            .most_missed(5)
+            .iter()
+            .map(|(key, stat)| {
+                format!(
+                    "{} {:.0}%",
+                    if *key == ' ' { '_' } else { *key },
+                    stat.error_rate() * 100.0
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        column![
+            toggle,
+            keyboard,
+            text(format!("Slowest pairs: {}", slowest)),
+            text(format!("Most missed: {}", most_missed)),
+        ]
+        .spacing(10)
+    }
+
+    /// The line being typed: what's done, what's wrong and what's left.
+    fn current_line_view(&self, remaining_color: iced::Color) -> Row<'_, Message> {
+        let runs = self
+            .session
+            .segments()
+            .into_iter()
+            .map(|(state, run)| {
+                let color = match state {
+                    CharState::Right => MATCHING_COLOR,
+                    CharState::Wrong => ERROR_COLOR,
+                    CharState::Left => remaining_color,
+                };
+                (color, run)
+            })
+            .collect();
+        marked_row(
+            runs,
+            &self.markers_on(self.text_controller_data.current_line),
+        )
+    }
+
+    /// A line of the page with its reading over it, and a button to change the reading on
+    /// lines that have one.
+    fn annotated_line<'a>(&'a self, line: usize, lyric: Row<'a, Message>) -> Element<'a, Message> {
+        let Some(reading) = self.readings.get(&line) else {
+            return lyric.into();
+        };
+        let label = match self.line_annotation(line) {
+            None => "-",
+            Some(Reading::Kana) => "あ",
+            Some(Reading::Romaji) => "a",
+        };
+        let mut lines = Column::new();
+        if let Some(reading) = reading {
+            lines = lines.push(text(reading).size(ANNOTATION_SIZE).style(|_| text::Style {
+                color: Some(WAITING_COLOR),
+            }));
+        }
+        row![
+            button(text(label).size(ANNOTATION_SIZE))
+                .padding(2)
+                .on_press(Message::CycleLineAnnotation(line)),
+            lines.push(lyric),
+        ]
+        .spacing(5)
+        .into()
+    }
+
+    /// The reading shown over a line of the page.
+    fn line_annotation(&self, line: usize) -> Option<Reading> {
+        self.line_annotations
+            .get(&line)
+            .copied()
+            .unwrap_or(self.annotation)
+    }
+
+    /// Works out the readings over the lines of the page, so they aren't redone on every
+    /// view.
+    fn update_readings(&mut self) {
+        self.readings.clear();
+        let CharControllerHandle::Ready(cc) = &self.char_controller_handle else {
+            return;
+        };
+        for (line, lyric) in self.text_controller_data.lyrics.iter().enumerate() {
+            if cc.has_readings(lyric) {
+                let reading = self
+                    .line_annotation(line)
+                    .map(|v| cc.reading(lyric, v, &self.romaji));
+                self.readings.insert(line, reading);
+            }
+        }
+    }
+
+    /// The ghost and the pace markers on a line of the page, as positions in the line.
+    fn markers_on(&self, line: usize) -> Vec<(usize, iced::Color)> {
+        [
+            (self.ghost_spot(), GHOST_COLOR),
+            (self.pace_spot(), PACE_COLOR),
+        ]
+        .into_iter()
+        .filter_map(|(spot, color)| spot.filter(|v| v.0 == line).map(|v| (v.1, color)))
+        .collect()
+    }
+
+    /// Where the pace is on the page, waiting at the start of the line until typing starts.
+    fn pace_spot(&self) -> Option<(usize, usize)> {
+        if self.replaying.is_some() {
+            return None;
+        }
+        match self.pace {
+            Some(pace) => Some(pace.spot(&self.text_controller_data.lyrics, self.now)),
+            None => self
+                .target_wpm
+                .map(|_| (self.text_controller_data.current_line, 0)),
+        }
+    }
+
+    /// Where the ghost is on the page being typed, as a line and a position in it.
+    fn ghost_spot(&self) -> Option<(usize, usize)> {
+        let ghost = self.ghost.as_ref().filter(|_| self.replaying.is_none())?;
+        // the ghost waits at the start until the typist sets off
+        let Some(elapsed) = self.recorder.elapsed(self.now) else {
+            return Some((0, 0));
+        };
+        let progress = ghost.progress_at(elapsed)?;
+        (progress.page == self.recorder.page()).then_some((progress.line, progress.position))
+    }
+
+    /// How far ahead of or behind the ghost the typist is, in time.
+    fn ghost_delta(&self) -> Option<String> {
+        let ghost = self.ghost.as_ref().filter(|_| self.replaying.is_none())?;
+        let elapsed = self.recorder.elapsed(self.now)?;
+        let progress = Progress {
+            page: self.recorder.page(),
+            line: self.text_controller_data.current_line,
+            position: self.session.position(),
+        };
+        Some(match ghost.time_to(progress) {
+            Some(t) if t >= elapsed => {
+                format!("{:.1}s ahead of your best", (t - elapsed).as_secs_f32())
+            }
+            Some(t) => format!("{:.1}s behind your best", (elapsed - t).as_secs_f32()),
+            None => "Past where your best run stopped".into(),
+        })
+    }
+
+    /// Looks for the best earlier run on the text being typed, to race against.
+    fn find_ghost(&self) -> Task<Message> {
+        if !self.race_ghost {
+            return Task::none();
+        }
+        let Some(key) = replay::text_key(
+            self.session_source,
+            self.session_song_id.as_deref(),
+            self.text_controller_data.title.as_deref(),
+        ) else {
+            return Task::none();
+        };
+        Task::perform(
+            async move {
+                replay::replay_dir()
+                    .ok()
+                    .and_then(|dir| replay::best_replay(&dir, &key))
+            },
+            Message::UpdateGhost,
+        )
+    }
+
+    fn init_text_controller(&self) -> Task<Message> {
+        if !matches!(self.text_controller_handle, TextControllerHandle::Loading) {
+            return Task::none();
+        }
+        Task::perform(
+            async {
+                let text_controller = TextController::init().await;
+                Ok(InitializerObject::Text(Arc::new(Mutex::new(
+                    text_controller,
+                ))))
+            },
+            Message::InitializeComplete,
+        )
+    }
+
+    /// How speed and accuracy have gone over the last days or weeks.
+    fn history_view(&self) -> Column<'_, Message> {
+        let period_label = match self.history_period {
+            TrendPeriod::Day => "By Day",
+            TrendPeriod::Week => "By Week",
+        };
+        let trends = history::trends(&self.history, self.history_period);
+        let mut trends_ui = Column::new().padding(10).spacing(4);
+        for trend in trends.iter().rev().take(HISTORY_PERIODS) {
+            trends_ui = trends_ui.push(row![
+                text(trend.label.clone()).width(Length::Fixed(150.0)),
+                // a bar as long as the speed, so the trend can be seen at a glance
+                container(Space::with_height(12))
+                    .width(Length::Fixed(trend.wpm.clamp(1.0, 150.0) * 2.0))
+                    .style(|_| container::Style {
+                        background: Some(MATCHING_COLOR.into()),
+                        ..Default::default()
+                    }),
+                Space::with_width(10),
+                text(format!(
+                    "{:.0} WPM, {:.0}%, {} errors, {} sessions, {:.0} min",
+                    trend.wpm,
+                    trend.accuracy * 100.0,
+                    trend.errors,
+                    trend.sessions,
+                    trend.duration / 60.0
+                )),
+            ]);
+        }
+        // everything typed across every run, the score included
+        let lifetime_score: usize = self.history.iter().map(|v| v.score).sum();
+        let lifetime_duration: f32 = self.history.iter().map(|v| v.duration).sum();
+        column![
+            text(format!(
+                "History ({} sessions, {} points, {:.0} min in all)",
+                self.history.len(),
+                lifetime_score,
+                lifetime_duration / 60.0
+            )),
+            row![button(period_label).on_press(Message::ToggleHistoryPeriod)],
+            trends_ui,
+        ]
+    }
+
+    fn cache_view(&self) -> Column<'_, Message> {
+        let mut cached_ui = Column::new().padding(10).spacing(10);
+        for cached in &self.cached_songs.songs {
+            cached_ui = cached_ui.push(row![
+                button(text(format!(
+                    "{} by {} ({} KB)",
+                    cached.track,
+                    cached.artist,
+                    cached.bytes.div_ceil(1024)
+                )))
+                .on_press(Message::PracticeCached(cached.clone())),
+                button("Remove").on_press(Message::RemoveCached(cached.key.clone())),
+            ]);
+        }
+        column![
+            text(format!(
+                "Cached Lyrics ({} songs, {} of {} KB)",
+                self.cached_songs.songs.len(),
+                self.cached_songs.bytes / 1024,
+                self.cached_songs.max_bytes / 1024
+            )),
+            row![
+                button("Refresh").on_press(Message::RefreshCache),
+                button("Clear Cache").on_press(Message::ClearCache),
+                button("Change Limit").on_press(Message::CycleCacheLimit),
+            ],
+            cached_ui,
+        ]
+    }
+
+    fn lyrics_candidates_view(&self) -> Column<'_, Message> {
+        if self.lyrics_candidates.is_empty() {
+            return Column::new();
+        }
+        let mut candidates_ui = Column::new().padding(10).spacing(10);
+        for (i, candidate) in self.lyrics_candidates.iter().enumerate() {
+            let duration = candidate.duration.round() as u32;
+            candidates_ui = candidates_ui.push(
+                button(text(format!(
+                    "{} by {} on {} ({}:{:02}{}) {:.0}% from {}",
+                    candidate.track,
+                    candidate.artist,
+                    candidate.album,
+                    duration / 60,
+                    duration % 60,
+                    if candidate.synced { ", synced" } else { "" },
+                    candidate.score.min(1.0) * 100.0,
+                    candidate.provider
+                )))
+                .on_press(Message::ChooseLyricsCandidate(i)),
+            );
+        }
+        // a song from the cache hasn't been looked up anywhere else yet
+        if self
+            .lyrics_candidates
+            .iter()
+            .all(|v| v.provider == CACHE_PROVIDER)
+        {
+            candidates_ui = candidates_ui
+                .push(button("Search Providers").on_press(Message::SearchLyricsProviders));
+        }
+        column![text("Lyrics Matches"), candidates_ui]
+    }
+
+    fn text_style_view(&self) -> Column<'_, Message> {
+        let order_label = match self.source_tree.order {
+            FileOrder::Random => "Random Order",
+            FileOrder::Sequential => "Sequential Order",
+        };
+        let indent_style_label = match self.source_tree.code.indent_style {
+            IndentStyle::Flat => "Flat Lines",
+            IndentStyle::Keep => "Keep Indent",
+            IndentStyle::Spaces => "Indent With Spaces",
+            IndentStyle::Tabs => "Indent With Tabs",
+        };
+        let indent_mode_label = match self.source_tree.code.indent_mode {
+            IndentMode::Skip => "Skip Indent",
+            IndentMode::Require => "Type Indent",
+        };
+        let strictness_label = match self.session.strictness() {
+            Strictness::StopOnError => "Stop On Errors",
+            Strictness::ContinueThroughErrors => "Type Through Errors",
+            Strictness::NoBackspace => "No Backspace",
+        };
+        let lesson_words_label = match self.lesson_words {
+            LessonWords::Pseudo => "Pseudo Words",
+            LessonWords::Real => "Real Words",
+        };
+        let lesson = Lesson::from_stats(self.session.key_stats(), self.lesson_words);
+        let mut romaji_row = row![text("Romaji")].spacing(5);
+        for system in RomajiSystem::ALL {
+            let label = if self.romaji.is_allowed(system) {
+                format!("{} On", system)
+            } else {
+                format!("{} Off", system)
+            };
+            romaji_row =
+                romaji_row.push(button(text(label)).on_press(Message::ToggleRomajiSystem(system)));
+        }
+        romaji_row = romaji_row.push(
+            button(text(format!("Hint In {}", self.romaji.hint())))
+                .on_press(Message::CycleRomajiHint),
+        );
+        let annotation_label = match self.annotation {
+            None => "No Readings",
+            Some(Reading::Kana) => "Kana Readings",
+            Some(Reading::Romaji) => "Romaji Readings",
+        };
+        romaji_row = romaji_row.push(button(annotation_label).on_press(Message::CycleAnnotation));
+        column![
+            text("Text Style"),
+            row![
+                button("LRCLIB").on_press(Message::SetLRCLIBText),
+                button("Synced LRCLIB").on_press(Message::SetSyncedLRCLIBText),
+                // button("Github").on_press(Message::SetGithubText),
+                button("Source File").on_press(Message::SetSourceFileText),
+                button("Lesson").on_press(Message::SetLessonText),
+            ],
+            row![
+                text_input(".lrc or .txt lyrics file", &self.lyrics_path)
+                    .width(Length::Fixed(300.0))
+                    .on_input(Message::LyricsPathChanged)
+                    .on_submit(Message::SetLocalFileText),
+                button("Load File").on_press(Message::SetLocalFileText),
+            ],
+            row![
+                text_input("Source folder", &self.source_tree.root.to_string_lossy())
+                    .width(Length::Fixed(300.0))
+                    .on_input(Message::SourceRootChanged)
+                    .on_submit(Message::SetSourceFileText),
+                text_input("Extensions", &self.source_tree.extensions)
+                    .width(Length::Fixed(100.0))
+                    .on_input(Message::SourceExtensionsChanged)
+                    .on_submit(Message::SetSourceFileText),
+                text_input("Glob", &self.source_tree.glob)
+                    .width(Length::Fixed(150.0))
+                    .on_input(Message::SourceGlobChanged)
+                    .on_submit(Message::SetSourceFileText),
+                button(order_label).on_press(Message::ToggleSourceOrder),
+            ],
+            row![
+                button(indent_style_label).on_press(Message::CycleIndentStyle),
+                button(text(format!(
+                    "Tab Width {}",
+                    self.source_tree.code.tab_width
+                )))
+                .on_press(Message::CycleTabWidth),
+                button(indent_mode_label).on_press(Message::ToggleIndentMode),
+                button(strictness_label).on_press(Message::CycleStrictness),
+                button(text(self.session_mode.to_string())).on_press(Message::CycleSessionMode),
+                text_input("Target WPM", &self.pace_input)
+                    .width(Length::Fixed(100.0))
+                    .on_input(Message::PaceChanged),
+            ],
+            row![
+                button(lesson_words_label).on_press(Message::ToggleLessonWords),
+                text(format!(
+                    "Lesson letters {}, focusing on {}",
+                    lesson.letters(),
+                    lesson.focus()
+                )),
+            ],
+            romaji_row,
+        ]
+    }
+
+    /// Whether the session mode's end has been reached.
+    fn run_end(&self) -> Option<EndReason> {
+        self.session_mode
+            .end_reason(self.recorder.elapsed(self.now), self.session.words_typed())
+    }
+
+    /// Ends the session and puts up its results.
+    fn end_run(&mut self, reason: EndReason) -> Task<Message> {
+        self.results = Some(Results {
+            mode: self.session_mode,
+            reason,
+            title: self.text_controller_data.title.clone(),
+            stats: self.session.stats(self.now),
+            metrics: self.session.metrics(self.now),
+            words: self.session.words_typed(),
+            score: self.score - self.session_start_score,
+            max_combo: self.rhythm.max_combo(),
+        });
+        self.pace = None;
+        self.finish_session()
+    }
+
+    fn results_view(&self, results: &Results) -> Column<'_, Message> {
+        let mut results_ui = column![
+            text("Results").size(32),
+            text(format!("{}: {}", results.mode, results.reason)),
+            text(results.title.clone().unwrap_or_default()),
+            text(format!(
+                "{:.0} WPM ({:.0} raw), {:.0} CPM",
+                results.metrics.wpm, results.metrics.raw_wpm, results.metrics.cpm
+            ))
+            .size(24),
+            text(format!(
+                "{:.0}% accuracy, {} errors",
+                results.metrics.accuracy * 100.0,
+                results.stats.errors
+            )),
+            text(format!(
+                "{} words over {} lines in {:.1}s",
+                results.words,
+                results.stats.lines,
+                results.stats.typing_time.as_secs_f32()
+            )),
+            text(format!("Score {}", results.score)),
+        ]
+        .spacing(10)
+        .padding(20);
+        if results.max_combo > 0 {
+            results_ui = results_ui.push(text(format!("Best combo {}", results.max_combo)));
+        }
+        results_ui.push(row![
+            button("Try Again").on_press(Message::LoadNewText),
+            button(text(self.session_mode.to_string())).on_press(Message::CycleSessionMode),
+        ])
+    }
+
+    /// Saves the session to the history if any line was finished, then starts a new one.
+    ///
+    /// Only finished lines count, so a line left half typed doesn't drag the speed down.
+    fn finish_session(&mut self) -> Task<Message> {
+        let stats = self.session.completed_stats();
+        let metrics = Metrics::new(
+            stats.keystrokes,
+            stats.keystrokes - stats.errors,
+            stats.typing_time,
+        );
+        let record = SessionRecord {
+            finished_at: storage::unix_now(),
+            source: self.session_source.into(),
+            song_id: self.session_song_id.clone(),
+            title: self.text_controller_data.title.clone(),
+            wpm: metrics.wpm,
+            accuracy: metrics.accuracy,
+            errors: stats.errors,
+            lines: stats.lines,
+            duration: stats.typing_time.as_secs_f32(),
+            score: self.score - self.session_start_score,
+        };
+        let replay = self.recorder.finish(
+            self.session_source.into(),
+            self.session_song_id.clone(),
+            self.text_controller_data.title.clone(),
+            self.session.strictness(),
+            self.source_tree.code.indent_mode,
+        );
+        self.session.reset();
+        self.session_start_score = self.score;
+        // a replay's keystrokes aren't the typist's
+        if self.replaying.is_none()
+            && let Err(e) = self.session.key_stats().save()
+        {
+            log::error!("could not save the key stats: {}", e);
+        }
+        if stats.lines == 0 {
+            return Task::none();
+        }
+        let save_history = Task::perform(
+            async move {
+                let history = match History::open() {
+                    Ok(history) => history,
+                    Err(e) => {
+                        log::error!("could not save the session: {}", e);
+                        return vec![];
+                    }
+                };
+                if let Err(e) = history.append(&record) {
+                    log::error!("could not save the session: {}", e);
+                }
+                history.load()
+            },
+            Message::UpdateHistory,
+        );
+        let Some(replay) = replay else {
+            return save_history;
+        };
+        let save_replay = Task::perform(
+            async move {
+                replay::replay_dir()
+                    .and_then(|dir| replay.save(&dir))
+                    .map_err(|e| log::error!("could not save the replay: {}", e))
+                    .ok()
+            },
+            Message::ReplaySaved,
+        );
+        Task::batch([save_history, save_replay])
+    }
+
+    /// Puts the text being typed aside and shows the replay in its place.
+    fn start_replay(&mut self, replay: Replay) {
+        self.stop_replay();
+        let now = Instant::now();
+        let mut session = TypingSession::new(replay.indent_mode);
+        session.set_strictness(replay.strictness);
+        let saved_session = std::mem::replace(&mut self.session, session);
+        let replay_text = TextControllerData {
+            text_type: self.text_controller_data.text_type.clone(),
+            title: Some(format!(
+                "Replay of {}",
+                replay.title.clone().unwrap_or(replay.source.clone())
+            )),
+            lyrics: replay.pages.first().cloned().unwrap_or_default(),
+            windows: vec![],
+            current_line: 0,
+            next_fetch_line: 0,
+        };
+        let saved_text = std::mem::replace(&mut self.text_controller_data, replay_text);
+        self.update_readings();
+        self.start_current_line();
+        self.replaying = Some(Replaying {
+            playback: Playback::new(replay, self.replay_speed, now),
+            origin: now,
+            page: 0,
+            saved_text,
+            saved_session,
+        });
+    }
+
+    /// Feeds the session every input change the replay has reached by `now`.
+    fn advance_replay(&mut self, now: Instant) {
+        let Some(replaying) = &mut self.replaying else {
+            return;
+        };
+        let origin = replaying.origin;
+        let page = replaying.page;
+        let events = replaying.playback.due(now);
+        for event in events {
+            if !replaying.playback.replay().has_line(event.page, event.line) {
+                continue;
+            }
+            if event.page != replaying.page || event.line != self.text_controller_data.current_line
+            {
+                replaying.page = event.page;
+                self.text_controller_data.lyrics = replaying
+                    .playback
+                    .replay()
+                    .pages
+                    .get(event.page)
+                    .cloned()
+                    .unwrap_or_default();
+                self.text_controller_data.current_line = event.line;
+                let line = self.text_controller_data.lyrics.get(event.line).cloned();
+                self.session.start_line(line.unwrap_or_default());
+            }
+            let at = origin + Duration::from_millis(event.at_ms);
+            if let InputResult::LineCompleted(_) = self.session.handle_input(event.value, at)
+                && self.text_controller_data.count_up()
+            {
+                let line = self
+                    .text_controller_data
+                    .lyrics
+                    .get(self.text_controller_data.current_line)
+                    .cloned();
+                self.session.start_line(line.unwrap_or_default());
+            }
+            if let CharControllerHandle::Ready(cc) = &self.char_controller_handle {
+                self.session.check_foreign_chars(cc, &self.romaji);
+            }
+        }
+        self.now = origin + replaying.playback.position(now);
+        if replaying.page != page {
+            self.update_readings();
+        }
+    }
+
+    /// Goes back to the text that was being typed before the replay.
+    fn stop_replay(&mut self) {
+        if let Some(replaying) = self.replaying.take() {
+            self.text_controller_data = replaying.saved_text;
+            self.session = replaying.saved_session;
+            self.update_readings();
+            self.now = Instant::now();
+        }
+    }
+
+    /// Watching a replay, or picking one to watch.
+    fn replay_view(&self) -> Row<'_, Message> {
+        let speed = button(text(format!("{}x Speed", self.replay_speed)))
+            .on_press(Message::CycleReplaySpeed);
+        if let Some(replaying) = &self.replaying {
+            let playback = &replaying.playback;
+            let position = playback
+                .position(Instant::now())
+                .min(playback.replay().duration());
+            let status = if playback.is_finished() {
+                "Replay finished".to_string()
+            } else {
+                format!(
+                    "Replaying {:.0}s of {:.0}s",
+                    position.as_secs_f32(),
+                    playback.replay().duration().as_secs_f32()
+                )
+            };
+            return row![
+                text(status),
+                Space::with_width(20),
+                speed,
+                button("Stop Replay").on_press(Message::StopReplay),
+            ];
+        }
+        let mut replay_row = row![
+            text_input("Replay file", &self.replay_path)
+                .width(Length::Fixed(300.0))
+                .on_input(Message::ReplayPathChanged)
+                .on_submit(Message::WatchReplay),
+            button("Watch Replay").on_press(Message::WatchReplay),
+        ];
+        if self.last_replay.is_some() {
+            replay_row =
+                replay_row.push(button("Watch Last Session").on_press(Message::WatchLastReplay));
+        }
+        let ghost_label = if self.race_ghost {
+            "Racing Your Best"
+        } else {
+            "Race Your Best"
+        };
+        replay_row
+            .push(speed)
+            .push(button(ghost_label).on_press(Message::ToggleGhost))
+    }
+
+    /// Hands the line the text is on to the session.
+    fn start_current_line(&mut self) {
+        let line = self
+            .text_controller_data
+            .lyrics
+            .get(self.text_controller_data.current_line)
+            .cloned()
+            .unwrap_or_default();
+        self.session.start_line(line);
+        self.rhythm.start_line();
+    }
+
+    /// How far into the song we are, once synced text has started.
+    fn song_time(&self) -> Option<Duration> {
+        self.song_clock.map(|clock| clock.position(Instant::now()))
+    }
+
     fn subscription(&self) -> Subscription<Message> {
-        time::every(Duration::from_millis(100)).map(Message::Tick)
+        // replays tick faster so keystrokes show up about when they happened
+        let tick = if self.replaying.is_some() { 20 } else { 100 };
+        // synced text keeps up with where Spotify really is, pauses and seeks included
+        let poll = if self.replaying.is_none()
+            && !self.text_controller_data.windows.is_empty()
+            && matches!(
+                self.spotify_controller_handle,
+                SpotifyControllerHandle::Ready(_)
+            ) {
+            time::every(PLAYBACK_POLL).map(|_| Message::PollPlayback)
+        } else {
+            Subscription::none()
+        };
+        Subscription::batch([
+            time::every(Duration::from_millis(tick)).map(Message::Tick),
+            window::close_requests().map(|_| Message::CloseRequested),
+            poll,
+        ])
     }
 
     fn theme(&self) -> Theme {
@@ -0,0 +1,567 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use crate::source_tree::IndentMode;
+use crate::storage;
+use crate::typing_session::Strictness;
+
+/// Bumped whenever the file format changes in a way older builds can't read.
+pub const REPLAY_VERSION: u32 = 1;
+
+/// One input change, as the session was handed it.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct InputEvent {
+    /// Milliseconds since the first keystroke.
+    pub at_ms: u64,
+    pub page: usize,
+    pub line: usize,
+    pub value: String,
+    /// How many characters into the line the typist was after this change.
+    pub position: usize,
+}
+
+impl InputEvent {
+    fn progress(&self) -> Progress {
+        Progress {
+            page: self.page,
+            line: self.line,
+            position: self.position,
+        }
+    }
+}
+
+/// How far into a text a run had got. Runs over the same text page it the same way, so
+/// these compare across runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Progress {
+    pub page: usize,
+    pub line: usize,
+    pub position: usize,
+}
+
+/// What makes two runs race on the same text: the song, or else the file or source it came
+/// from. Lessons are made up fresh every time, so they never match.
+pub fn text_key(source: &str, song_id: Option<&str>, title: Option<&str>) -> Option<String> {
+    if source == "Lesson" {
+        return None;
+    }
+    match (song_id, title) {
+        (Some(id), _) => Some(format!("song:{}", id)),
+        (None, Some(title)) => Some(format!("{}:{}", source, title)),
+        (None, None) => None,
+    }
+}
+
+/// Everything needed to play a session back: the pages of lines it went through and every
+/// input change made on them.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    /// Unix seconds.
+    pub recorded_at: u64,
+    pub source: String,
+    pub song_id: Option<String>,
+    pub title: Option<String>,
+    pub strictness: Strictness,
+    pub indent_mode: IndentMode,
+    pub pages: Vec<Vec<String>>,
+    pub events: Vec<InputEvent>,
+}
+
+impl Replay {
+    pub fn duration(&self) -> Duration {
+        self.events
+            .last()
+            .map(|v| Duration::from_millis(v.at_ms))
+            .unwrap_or_default()
+    }
+
+    pub fn text_key(&self) -> Option<String> {
+        text_key(&self.source, self.song_id.as_deref(), self.title.as_deref())
+    }
+
+    /// Where the run was at `at` into it.
+    pub fn progress_at(&self, at: Duration) -> Option<Progress> {
+        let at = at.as_millis() as u64;
+        self.events
+            .iter()
+            .take_while(|v| v.at_ms <= at)
+            .map(|v| v.progress())
+            .max()
+    }
+
+    /// When the run first got as far as `progress`, if it ever did.
+    pub fn time_to(&self, progress: Progress) -> Option<Duration> {
+        self.events
+            .iter()
+            .find(|v| v.progress() >= progress)
+            .map(|v| Duration::from_millis(v.at_ms))
+    }
+
+    /// Characters per second up to the furthest point the run got to.
+    pub fn speed(&self) -> f32 {
+        let Some(furthest) = self.events.iter().map(|v| v.progress()).max() else {
+            return 0.0;
+        };
+        let seconds = self.duration().as_secs_f32();
+        if seconds <= 0.0 {
+            return 0.0;
+        }
+        self.chars_before(furthest) as f32 / seconds
+    }
+
+    fn chars_before(&self, progress: Progress) -> usize {
+        let lines = self.pages.iter().enumerate().flat_map(|(page, lines)| {
+            lines
+                .iter()
+                .enumerate()
+                .map(move |(line, text)| ((page, line), text))
+        });
+        lines
+            .take_while(|((page, line), _)| (*page, *line) < (progress.page, progress.line))
+            .map(|(_, text)| text.chars().count())
+            .sum::<usize>()
+            + progress.position
+    }
+
+    /// Whether `line` of `page` is one of the lines the replay went through.
+    pub fn has_line(&self, page: usize, line: usize) -> bool {
+        self.pages.get(page).is_some_and(|v| line < v.len())
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let source = fs::read_to_string(path)?;
+        let version: serde_json::Value = serde_json::from_str(&source)?;
+        match version.get("version").and_then(|v| v.as_u64()) {
+            Some(v) if v <= REPLAY_VERSION as u64 => {
+                let replay: Replay = serde_json::from_value(version)?;
+                if let Some(event) = replay
+                    .events
+                    .iter()
+                    .find(|v| !replay.has_line(v.page, v.line))
+                {
+                    return Err(anyhow::anyhow!(
+                        "replay has input on line {} of page {}, which it doesn't have",
+                        event.line,
+                        event.page
+                    ));
+                }
+                Ok(replay)
+            }
+            Some(v) => Err(anyhow::anyhow!(
+                "replay is version {}, this build reads up to {}",
+                v,
+                REPLAY_VERSION
+            )),
+            None => Err(anyhow::anyhow!("not a replay file")),
+        }
+    }
+
+    /// Writes the replay into `dir` under a name made from when and what was typed.
+    pub fn save(&self, dir: &Path) -> anyhow::Result<PathBuf> {
+        fs::create_dir_all(dir)?;
+        let name = format!(
+            "{}-{}.json",
+            self.recorded_at,
+            storage::file_stem(self.title.as_deref().unwrap_or(&self.source))
+        );
+        let path = dir.join(name);
+        fs::write(&path, serde_json::to_string(self)?)?;
+        Ok(path)
+    }
+}
+
+/// Where finished sessions are saved as replays.
+pub fn replay_dir() -> anyhow::Result<PathBuf> {
+    Ok(storage::data_dir()?.join("replays"))
+}
+
+/// The fastest run saved in `dir` on the text with `key`, to race against.
+pub fn best_replay(dir: &Path, key: &str) -> Option<Replay> {
+    let files = fs::read_dir(dir).ok()?;
+    files
+        .filter_map(Result::ok)
+        .filter_map(|file| match Replay::load(&file.path()) {
+            Ok(replay) => Some(replay),
+            Err(e) => {
+                log::warn!("skipping replay {}: {}", file.path().display(), e);
+                None
+            }
+        })
+        .filter(|v| v.text_key().as_deref() == Some(key))
+        .max_by(|a, b| a.speed().total_cmp(&b.speed()))
+}
+
+/// Builds up a replay while a session is typed.
+#[derive(Debug, Clone, Default)]
+pub struct Recorder {
+    pages: Vec<Vec<String>>,
+    events: Vec<InputEvent>,
+    started: Option<Instant>,
+}
+
+impl Recorder {
+    /// A new page of lines is being typed, which the following lines index into.
+    pub fn add_page(&mut self, lines: Vec<String>) {
+        self.pages.push(lines);
+    }
+
+    /// The page being typed, counting from the first of the session.
+    pub fn page(&self) -> usize {
+        self.pages.len().saturating_sub(1)
+    }
+
+    /// How long since the first keystroke of the session.
+    pub fn elapsed(&self, now: Instant) -> Option<Duration> {
+        self.started
+            .map(|started| now.saturating_duration_since(started))
+    }
+
+    /// Records the input the session was handed, and how far into the line it got.
+    pub fn record(&mut self, line: usize, value: &str, position: usize, at: Instant) {
+        let Some(page) = self.pages.len().checked_sub(1) else {
+            return;
+        };
+        let started = *self.started.get_or_insert(at);
+        self.events.push(InputEvent {
+            at_ms: at.saturating_duration_since(started).as_millis() as u64,
+            page,
+            line,
+            value: value.to_owned(),
+            position,
+        });
+    }
+
+    /// Hands over what was recorded and starts over, or `None` if nothing was typed.
+    pub fn finish(
+        &mut self,
+        source: String,
+        song_id: Option<String>,
+        title: Option<String>,
+        strictness: Strictness,
+        indent_mode: IndentMode,
+    ) -> Option<Replay> {
+        let recorder = std::mem::take(self);
+        if recorder.events.is_empty() {
+            return None;
+        }
+        Some(Replay {
+            version: REPLAY_VERSION,
+            recorded_at: storage::unix_now(),
+            source,
+            song_id,
+            title,
+            strictness,
+            indent_mode,
+            pages: recorder.pages,
+            events: recorder.events,
+        })
+    }
+}
+
+/// Plays a replay back against the clock, at any speed.
+#[derive(Debug, Clone)]
+pub struct Playback {
+    replay: Replay,
+    speed: f32,
+    // where the playback was at `resumed`
+    offset: Duration,
+    resumed: Instant,
+    next_event: usize,
+}
+
+impl Playback {
+    pub fn new(replay: Replay, speed: f32, now: Instant) -> Self {
+        Self {
+            replay,
+            speed,
+            offset: Duration::ZERO,
+            resumed: now,
+            next_event: 0,
+        }
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    /// Changes speed without jumping, so the playback carries on from where it is.
+    pub fn set_speed(&mut self, speed: f32, now: Instant) {
+        self.offset = self.position(now);
+        self.resumed = now;
+        self.speed = speed;
+    }
+
+    /// How far into the recording the playback is.
+    pub fn position(&self, now: Instant) -> Duration {
+        self.offset
+            + now
+                .saturating_duration_since(self.resumed)
+                .mul_f32(self.speed)
+    }
+
+    /// The events that have come due since the last call, in order.
+    pub fn due(&mut self, now: Instant) -> Vec<InputEvent> {
+        let position = self.position(now).as_millis() as u64;
+        let start = self.next_event;
+        while self
+            .replay
+            .events
+            .get(self.next_event)
+            .is_some_and(|v| v.at_ms <= position)
+        {
+            self.next_event += 1;
+        }
+        self.replay.events[start..self.next_event].to_vec()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next_event >= self.replay.events.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(at_ms: u64, line: usize, value: &str) -> InputEvent {
+        InputEvent {
+            at_ms,
+            page: 0,
+            line,
+            value: value.into(),
+            position: value.chars().count(),
+        }
+    }
+
+    fn replay(events: Vec<InputEvent>) -> Replay {
+        Replay {
+            version: REPLAY_VERSION,
+            recorded_at: 0,
+            source: "File".into(),
+            song_id: None,
+            title: Some("Title".into()),
+            strictness: Strictness::StopOnError,
+            indent_mode: IndentMode::Skip,
+            pages: vec![vec!["ab".into(), "cd".into()]],
+            events,
+        }
+    }
+
+    /// Saves `value` as a replay file of its own in the temp dir and loads it back.
+    fn load(name: &str, value: serde_json::Value) -> anyhow::Result<Replay> {
+        let path = std::env::temp_dir().join(format!(
+            "iced_musical_typing-{}-{}.json",
+            name,
+            std::process::id()
+        ));
+        fs::write(&path, value.to_string()).unwrap();
+        let replay = Replay::load(&path);
+        let _ = fs::remove_file(&path);
+        replay
+    }
+
+    /// Two pages, with a backspace on the second line of the first.
+    fn two_pages() -> Replay {
+        let at = |at_ms, page, line, value: &str| InputEvent {
+            at_ms,
+            page,
+            line,
+            value: value.into(),
+            position: value.chars().count(),
+        };
+        Replay {
+            pages: vec![vec!["ab".into(), "cd".into()], vec!["efg".into()]],
+            ..replay(vec![
+                at(0, 0, 0, "a"),
+                at(100, 0, 0, "ab"),
+                at(200, 0, 1, "c"),
+                at(500, 0, 1, ""),
+                at(1000, 1, 0, "ef"),
+            ])
+        }
+    }
+
+    fn progress(page: usize, line: usize, position: usize) -> Progress {
+        Progress {
+            page,
+            line,
+            position,
+        }
+    }
+
+    #[test]
+    fn progress_at_is_the_furthest_point_so_far() {
+        let run = two_pages();
+        assert_eq!(run.progress_at(Duration::ZERO), Some(progress(0, 0, 1)));
+        assert_eq!(
+            run.progress_at(Duration::from_millis(150)),
+            Some(progress(0, 0, 2))
+        );
+        assert_eq!(
+            run.progress_at(Duration::from_millis(600)),
+            Some(progress(0, 1, 1))
+        );
+        assert_eq!(
+            run.progress_at(Duration::from_secs(5)),
+            Some(progress(1, 0, 2))
+        );
+        assert_eq!(replay(vec![]).progress_at(Duration::from_secs(1)), None);
+    }
+
+    #[test]
+    fn time_to_is_when_the_run_first_got_there() {
+        let run = two_pages();
+        assert_eq!(
+            run.time_to(progress(0, 1, 0)),
+            Some(Duration::from_millis(200))
+        );
+        assert_eq!(
+            run.time_to(progress(0, 1, 1)),
+            Some(Duration::from_millis(200))
+        );
+        assert_eq!(
+            run.time_to(progress(1, 0, 1)),
+            Some(Duration::from_millis(1000))
+        );
+        assert_eq!(run.time_to(progress(1, 0, 3)), None);
+    }
+
+    #[test]
+    fn chars_before_counts_every_earlier_line_of_every_page() {
+        let run = two_pages();
+        assert_eq!(run.chars_before(progress(0, 0, 0)), 0);
+        assert_eq!(run.chars_before(progress(0, 1, 1)), 3);
+        assert_eq!(run.chars_before(progress(1, 0, 2)), 6);
+    }
+
+    #[test]
+    fn speed_is_characters_per_second_to_the_furthest_point() {
+        assert_eq!(two_pages().speed(), 6.0);
+        assert_eq!(replay(vec![]).speed(), 0.0);
+        assert_eq!(replay(vec![event(0, 0, "a")]).speed(), 0.0);
+    }
+
+    #[test]
+    fn recorder_times_events_from_the_first_keystroke() {
+        let start = Instant::now();
+        let mut recorder = Recorder::default();
+        recorder.record(0, "a", 1, start);
+        recorder.add_page(vec!["ab".into()]);
+        recorder.add_page(vec!["cd".into()]);
+        recorder.record(0, "c", 1, start + Duration::from_millis(100));
+        recorder.record(0, "cd", 2, start + Duration::from_millis(350));
+        assert_eq!(recorder.page(), 1);
+        assert_eq!(
+            recorder.elapsed(start + Duration::from_millis(400)),
+            Some(Duration::from_millis(300))
+        );
+
+        let replay = recorder
+            .finish(
+                "File".into(),
+                None,
+                None,
+                Strictness::StopOnError,
+                IndentMode::Skip,
+            )
+            .unwrap();
+        let times: Vec<_> = replay.events.iter().map(|v| (v.at_ms, v.page)).collect();
+        assert_eq!(times, [(0, 1), (250, 1)]);
+        assert_eq!(replay.pages.len(), 2);
+    }
+
+    #[test]
+    fn recorder_starts_over_after_finishing() {
+        let mut recorder = Recorder::default();
+        recorder.add_page(vec!["ab".into()]);
+        recorder.record(0, "a", 1, Instant::now());
+        let finish = |recorder: &mut Recorder| {
+            recorder.finish(
+                "File".into(),
+                None,
+                None,
+                Strictness::StopOnError,
+                IndentMode::Skip,
+            )
+        };
+        assert!(finish(&mut recorder).is_some());
+        assert_eq!(finish(&mut recorder), None);
+        assert_eq!(recorder.elapsed(Instant::now()), None);
+    }
+
+    #[test]
+    fn due_hands_over_each_event_once_in_order() {
+        let start = Instant::now();
+        let events = vec![event(0, 0, "a"), event(100, 0, "ab"), event(300, 1, "c")];
+        let mut playback = Playback::new(replay(events.clone()), 1.0, start);
+        assert_eq!(playback.due(start), events[..1]);
+        assert_eq!(playback.due(start), []);
+        assert_eq!(
+            playback.due(start + Duration::from_millis(299)),
+            events[1..2]
+        );
+        assert!(!playback.is_finished());
+        assert_eq!(playback.due(start + Duration::from_secs(1)), events[2..]);
+        assert!(playback.is_finished());
+    }
+
+    #[test]
+    fn position_runs_at_the_playback_speed() {
+        let start = Instant::now();
+        let playback = Playback::new(replay(vec![]), 2.0, start);
+        assert_eq!(
+            playback.position(start + Duration::from_millis(500)),
+            Duration::from_secs(1)
+        );
+    }
+
+    #[test]
+    fn set_speed_carries_on_from_where_it_was() {
+        let start = Instant::now();
+        let mut playback = Playback::new(replay(vec![]), 1.0, start);
+        let changed = start + Duration::from_secs(1);
+        playback.set_speed(0.5, changed);
+        assert_eq!(playback.position(changed), Duration::from_secs(1));
+        assert_eq!(
+            playback.position(changed + Duration::from_secs(2)),
+            Duration::from_secs(2)
+        );
+    }
+
+    #[test]
+    fn load_reads_back_what_was_saved() {
+        let saved = replay(vec![event(0, 0, "a"), event(100, 1, "c")]);
+        let loaded = load("replay-roundtrip", serde_json::to_value(&saved).unwrap());
+        assert_eq!(loaded.unwrap(), saved);
+    }
+
+    #[test]
+    fn load_refuses_newer_versions() {
+        let mut value = serde_json::to_value(replay(vec![])).unwrap();
+        value["version"] = (REPLAY_VERSION + 1).into();
+        assert!(load("replay-newer", value).is_err());
+    }
+
+    #[test]
+    fn load_refuses_files_without_a_version() {
+        let mut value = serde_json::to_value(replay(vec![])).unwrap();
+        value.as_object_mut().unwrap().remove("version");
+        assert!(load("replay-unversioned", value).is_err());
+    }
+
+    #[test]
+    fn load_refuses_events_past_the_pages() {
+        let past_line = replay(vec![event(0, 2, "a")]);
+        let value = serde_json::to_value(&past_line).unwrap();
+        assert!(load("replay-past-line", value).is_err());
+
+        let mut past_page = replay(vec![event(0, 0, "a")]);
+        past_page.events[0].page = 1;
+        let value = serde_json::to_value(&past_page).unwrap();
+        assert!(load("replay-past-page", value).is_err());
+    }
+}
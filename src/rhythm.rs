@@ -0,0 +1,254 @@
+use std::time::{Duration, Instant};
+
+use crate::lrc::SyncWindow;
+
+// how far from its beat a word can be finished for each judgment
+const PERFECT_WINDOW: Duration = Duration::from_millis(300);
+const GREAT_WINDOW: Duration = Duration::from_millis(700);
+const GOOD_WINDOW: Duration = Duration::from_millis(1500);
+/// Every this many hits in a row raise the multiplier by one.
+const COMBO_STEP: usize = 10;
+const MAX_MULTIPLIER: usize = 4;
+/// How long a judgment stays up in the info row.
+const POPUP_TIME: Duration = Duration::from_millis(800);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Judgment {
+    Perfect,
+    Great,
+    Good,
+    Miss,
+}
+
+impl Judgment {
+    /// Judges a word finished at `finished` (song time) against when it was due.
+    pub fn judge(due: Duration, finished: Duration) -> Self {
+        let offset = finished.abs_diff(due);
+        if offset <= PERFECT_WINDOW {
+            Judgment::Perfect
+        } else if offset <= GREAT_WINDOW {
+            Judgment::Great
+        } else if offset <= GOOD_WINDOW {
+            Judgment::Good
+        } else {
+            Judgment::Miss
+        }
+    }
+
+    /// Points before the multiplier.
+    pub fn points(self) -> usize {
+        match self {
+            Judgment::Perfect => 10,
+            Judgment::Great => 6,
+            Judgment::Good => 3,
+            Judgment::Miss => 0,
+        }
+    }
+}
+
+impl std::fmt::Display for Judgment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Judgment::Perfect => write!(f, "Perfect!"),
+            Judgment::Great => write!(f, "Great"),
+            Judgment::Good => write!(f, "Good"),
+            Judgment::Miss => write!(f, "Miss"),
+        }
+    }
+}
+
+/// When each word of a synced line is due, as the index of the space that ends it and a
+/// song time.
+///
+/// LRC only times whole lines, so the window is spread over the line's characters and a
+/// word is due when the singer would get to its end. The last word is due as the window
+/// closes.
+pub fn word_beats(line: &str, window: SyncWindow) -> Vec<(usize, Duration)> {
+    let chars: Vec<char> = line.chars().collect();
+    let span = window.end.saturating_sub(window.start);
+    (1..chars.len())
+        .filter(|i| chars[*i] == ' ' && chars[i - 1] != ' ')
+        .map(|i| {
+            (
+                i,
+                window.start + span.mul_f32((i + 1) as f32 / chars.len() as f32),
+            )
+        })
+        .collect()
+}
+
+/// Judgments, combo and multiplier for typing along to synced lyrics.
+#[derive(Debug, Clone, Default)]
+pub struct Rhythm {
+    combo: usize,
+    max_combo: usize,
+    // the words of the current line up to here have been judged
+    judged: usize,
+    last: Option<(Judgment, Instant)>,
+}
+
+impl Rhythm {
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn start_line(&mut self) {
+        self.judged = 0;
+    }
+
+    pub fn combo(&self) -> usize {
+        self.combo
+    }
+
+    pub fn max_combo(&self) -> usize {
+        self.max_combo
+    }
+
+    pub fn multiplier(&self) -> usize {
+        (1 + self.combo / COMBO_STEP).min(MAX_MULTIPLIER)
+    }
+
+    /// The latest judgment, while it should still be on screen.
+    pub fn popup(&self, now: Instant) -> Option<Judgment> {
+        self.last
+            .filter(|(_, at)| now.saturating_duration_since(*at) <= POPUP_TIME)
+            .map(|v| v.0)
+    }
+
+    /// Judges each word of the line that `position` has moved past since last time, and
+    /// returns the points they earned.
+    pub fn judge_words(
+        &mut self,
+        line: &str,
+        window: SyncWindow,
+        position: usize,
+        song_time: Duration,
+        now: Instant,
+    ) -> usize {
+        let mut points = 0;
+        for (end, due) in word_beats(line, window) {
+            if end >= self.judged && end < position {
+                points += self.add(Judgment::judge(due, song_time), now);
+            }
+        }
+        self.judged = self.judged.max(position);
+        points
+    }
+
+    /// Every word of the line not typed yet is a miss, for when the line is skipped or
+    /// the song moves on without it.
+    pub fn miss_rest(&mut self, line: &str, window: SyncWindow, now: Instant) {
+        for (end, _) in word_beats(line, window) {
+            if end >= self.judged {
+                self.add(Judgment::Miss, now);
+            }
+        }
+        self.judged = line.chars().count();
+    }
+
+    fn add(&mut self, judgment: Judgment, now: Instant) -> usize {
+        self.last = Some((judgment, now));
+        if judgment == Judgment::Miss {
+            self.combo = 0;
+            return 0;
+        }
+        let points = judgment.points() * self.multiplier();
+        self.combo += 1;
+        self.max_combo = self.max_combo.max(self.combo);
+        points
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DUE: Duration = Duration::from_secs(10);
+
+    fn window() -> SyncWindow {
+        SyncWindow {
+            start: Duration::ZERO,
+            end: Duration::from_secs(4),
+        }
+    }
+
+    #[test]
+    fn windows_hold_on_either_side_of_the_beat() {
+        let ms = Duration::from_millis;
+        for (offset, judgment) in [
+            (ms(0), Judgment::Perfect),
+            (PERFECT_WINDOW, Judgment::Perfect),
+            (PERFECT_WINDOW + ms(1), Judgment::Great),
+            (GREAT_WINDOW, Judgment::Great),
+            (GREAT_WINDOW + ms(1), Judgment::Good),
+            (GOOD_WINDOW, Judgment::Good),
+            (GOOD_WINDOW + ms(1), Judgment::Miss),
+        ] {
+            assert_eq!(
+                Judgment::judge(DUE, DUE - offset),
+                judgment,
+                "early {offset:?}"
+            );
+            assert_eq!(
+                Judgment::judge(DUE, DUE + offset),
+                judgment,
+                "late {offset:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn words_are_due_at_their_end() {
+        // eight characters over four seconds, half a second each
+        assert_eq!(
+            word_beats("ab cd ef", window()),
+            [
+                (2, Duration::from_millis(1500)),
+                (5, Duration::from_secs(3))
+            ]
+        );
+    }
+
+    #[test]
+    fn each_word_is_judged_once() {
+        let now = Instant::now();
+        let mut rhythm = Rhythm::default();
+        let on_time = Duration::from_millis(1500);
+        assert_eq!(
+            rhythm.judge_words("ab cd ef", window(), 3, on_time, now),
+            10
+        );
+        assert_eq!(rhythm.judge_words("ab cd ef", window(), 4, on_time, now), 0);
+        assert_eq!(rhythm.combo(), 1);
+        assert_eq!(rhythm.popup(now), Some(Judgment::Perfect));
+        assert_eq!(rhythm.popup(now + POPUP_TIME * 2), None);
+    }
+
+    #[test]
+    fn a_miss_resets_the_combo_and_multiplier() {
+        let now = Instant::now();
+        let mut rhythm = Rhythm::default();
+        for _ in 0..COMBO_STEP {
+            rhythm.add(Judgment::Great, now);
+        }
+        assert_eq!(rhythm.multiplier(), 2);
+        assert_eq!(rhythm.add(Judgment::Perfect, now), 20);
+
+        rhythm.start_line();
+        rhythm.miss_rest("ab cd ef", window(), now);
+        assert_eq!(rhythm.combo(), 0);
+        assert_eq!(rhythm.multiplier(), 1);
+        assert_eq!(rhythm.max_combo(), COMBO_STEP + 1);
+        assert_eq!(rhythm.popup(now), Some(Judgment::Miss));
+    }
+
+    #[test]
+    fn the_multiplier_tops_out() {
+        let now = Instant::now();
+        let mut rhythm = Rhythm::default();
+        for _ in 0..COMBO_STEP * (MAX_MULTIPLIER + 2) {
+            rhythm.add(Judgment::Good, now);
+        }
+        assert_eq!(rhythm.multiplier(), MAX_MULTIPLIER);
+    }
+}
@@ -0,0 +1,41 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A xorshift generator seeded from the clock, plenty for shuffling practice files and
+/// picking practice words.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn from_clock() -> Self {
+        Self(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|v| v.as_nanos() as u64)
+                .unwrap_or(1)
+                | 1,
+        )
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    pub fn below(&mut self, len: usize) -> usize {
+        (self.next() % len.max(1) as u64) as usize
+    }
+
+    /// An index into `weights`, each picked in proportion to its weight.
+    pub fn pick_weighted(&mut self, weights: &[f32]) -> usize {
+        let total: f32 = weights.iter().sum();
+        let mut target = (self.next() >> 11) as f32 / (1u64 << 53) as f32 * total;
+        for (i, weight) in weights.iter().enumerate() {
+            if target < *weight {
+                return i;
+            }
+            target -= weight;
+        }
+        weights.len().saturating_sub(1)
+    }
+}
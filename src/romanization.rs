@@ -0,0 +1,245 @@
+/// A way of writing kana in latin letters. The kana maps are in Hepburn, and the others are
+/// worked out from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RomajiSystem {
+    /// `shi`, `chi`, `tsu`, `fu`, `ja`.
+    Hepburn,
+    /// `si`, `ti`, `tu`, `hu`, `zya`.
+    Kunrei,
+    /// Like Kunrei, but `ぢ` and `づ` keep their `d`: `di`, `du`, `dya`.
+    Nihon,
+    /// What IMEs take on top of all of those: small kana on their own with `x` or `l`, like
+    /// `xtu`, `ltu` or `texi` for `ティ`, and `jya` for `じゃ`.
+    Ime,
+}
+
+impl RomajiSystem {
+    pub const ALL: [RomajiSystem; 4] = [
+        RomajiSystem::Hepburn,
+        RomajiSystem::Kunrei,
+        RomajiSystem::Nihon,
+        RomajiSystem::Ime,
+    ];
+
+    /// `hepburn` in this system. It can be a whole reading, like `shichi` for 七.
+    pub fn spell(self, hepburn: &str) -> Vec<String> {
+        match self {
+            RomajiSystem::Hepburn => vec![hepburn.to_owned()],
+            RomajiSystem::Kunrei => vec![convert(hepburn, KUNREI)],
+            RomajiSystem::Nihon => vec![convert(hepburn, NIHON)],
+            RomajiSystem::Ime => vec![
+                hepburn.to_owned(),
+                convert(hepburn, KUNREI),
+                convert(hepburn, NIHON),
+                convert(hepburn, IME),
+            ],
+        }
+    }
+}
+
+impl std::fmt::Display for RomajiSystem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RomajiSystem::Hepburn => write!(f, "Hepburn"),
+            RomajiSystem::Kunrei => write!(f, "Kunrei"),
+            RomajiSystem::Nihon => write!(f, "Nihon"),
+            RomajiSystem::Ime => write!(f, "IME"),
+        }
+    }
+}
+
+// Hepburn syllables and what they become, longest first so `sha` wins over `sh`
+const KUNREI: &[(&str, &str)] = &[
+    ("sha", "sya"),
+    ("shu", "syu"),
+    ("she", "sye"),
+    ("sho", "syo"),
+    ("shi", "si"),
+    ("cha", "tya"),
+    ("chu", "tyu"),
+    ("che", "tye"),
+    ("cho", "tyo"),
+    ("chi", "ti"),
+    ("tsu", "tu"),
+    ("fu", "hu"),
+    ("ja", "zya"),
+    ("ju", "zyu"),
+    ("je", "zye"),
+    ("jo", "zyo"),
+    ("ji", "zi"),
+    ("di", "zi"),
+    ("du", "zu"),
+];
+const NIHON: &[(&str, &str)] = &[
+    ("sha", "sya"),
+    ("shu", "syu"),
+    ("she", "sye"),
+    ("sho", "syo"),
+    ("shi", "si"),
+    ("cha", "tya"),
+    ("chu", "tyu"),
+    ("che", "tye"),
+    ("cho", "tyo"),
+    ("chi", "ti"),
+    ("tsu", "tu"),
+    ("fu", "hu"),
+    ("ja", "zya"),
+    ("ju", "zyu"),
+    ("je", "zye"),
+    ("jo", "zyo"),
+    ("ji", "zi"),
+];
+const IME: &[(&str, &str)] = &[("ja", "jya"), ("ju", "jyu"), ("je", "jye"), ("jo", "jyo")];
+
+/// Swaps every syllable of `hepburn` found in `table`, leaving the rest as it is.
+fn convert(hepburn: &str, table: &[(&str, &str)]) -> String {
+    let mut out = String::new();
+    let mut rest = hepburn;
+    while let Some(next) = rest.chars().next() {
+        match table.iter().find(|(from, _)| rest.starts_with(from)) {
+            Some((from, to)) => {
+                out.push_str(to);
+                rest = &rest[from.len()..];
+            }
+            None => {
+                out.push(next);
+                rest = &rest[next.len_utf8()..];
+            }
+        }
+    }
+    out
+}
+
+/// Which romaji systems are accepted, and which one the hints are shown in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RomajiSettings {
+    allowed: Vec<RomajiSystem>,
+    hint: RomajiSystem,
+}
+
+impl Default for RomajiSettings {
+    fn default() -> Self {
+        Self {
+            allowed: RomajiSystem::ALL.to_vec(),
+            hint: RomajiSystem::Hepburn,
+        }
+    }
+}
+
+impl RomajiSettings {
+    pub fn is_allowed(&self, system: RomajiSystem) -> bool {
+        self.allowed.contains(&system)
+    }
+
+    pub fn hint(&self) -> RomajiSystem {
+        self.hint
+    }
+
+    /// Allows or disallows `system`. The last allowed one can't be turned off, and the
+    /// hint moves to another if its system is.
+    pub fn toggle(&mut self, system: RomajiSystem) {
+        if !self.is_allowed(system) {
+            self.allowed.push(system);
+        } else if self.allowed.len() > 1 {
+            self.allowed.retain(|v| *v != system);
+            if self.hint == system {
+                self.cycle_hint();
+            }
+        }
+    }
+
+    /// Moves the hint on to the next allowed system.
+    pub fn cycle_hint(&mut self) {
+        let start = RomajiSystem::ALL
+            .iter()
+            .position(|v| *v == self.hint)
+            .unwrap_or(0);
+        if let Some(next) = (1..=RomajiSystem::ALL.len())
+            .map(|i| RomajiSystem::ALL[(start + i) % RomajiSystem::ALL.len()])
+            .find(|v| self.is_allowed(*v))
+        {
+            self.hint = next;
+        }
+    }
+
+    /// Every accepted spelling of the Hepburn `spellings`, with the hint system's first.
+    pub fn spell(&self, spellings: &[String]) -> Vec<String> {
+        let systems = std::iter::once(self.hint).chain(
+            RomajiSystem::ALL
+                .into_iter()
+                .filter(|v| *v != self.hint && self.is_allowed(*v)),
+        );
+        let mut out: Vec<String> = vec![];
+        for system in systems {
+            for spelling in spellings {
+                for spelling in system.spell(spelling) {
+                    if !out.contains(&spelling) {
+                        out.push(spelling);
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spell(system: RomajiSystem, hepburn: &str) -> String {
+        system.spell(hepburn).remove(0)
+    }
+
+    #[test]
+    fn each_system_spells_the_kana_hepburn_spells_its_own_way() {
+        // し, ち, つ, ふ, じ, じゃ and ん before a vowel
+        let words = ["shi", "chi", "tsu", "fu", "ji", "ja", "fun'iki"];
+        let kunrei = ["si", "ti", "tu", "hu", "zi", "zya", "hun'iki"];
+        for (system, expected) in [
+            (RomajiSystem::Hepburn, words),
+            (RomajiSystem::Kunrei, kunrei),
+            (RomajiSystem::Nihon, kunrei),
+        ] {
+            let spelled = words.map(|v| spell(system, v));
+            assert_eq!(spelled, expected, "{}", system);
+        }
+    }
+
+    #[test]
+    fn only_nihon_keeps_the_d_of_ji_and_zu() {
+        assert_eq!(spell(RomajiSystem::Kunrei, "di"), "zi");
+        assert_eq!(spell(RomajiSystem::Kunrei, "du"), "zu");
+        assert_eq!(spell(RomajiSystem::Nihon, "di"), "di");
+        assert_eq!(spell(RomajiSystem::Nihon, "du"), "du");
+    }
+
+    #[test]
+    fn ime_takes_every_system_and_jya() {
+        let spellings = RomajiSystem::Ime.spell("shija");
+        for expected in ["shija", "sizya", "shijya"] {
+            assert!(spellings.iter().any(|v| v == expected), "{}", expected);
+        }
+    }
+
+    #[test]
+    fn settings_put_the_hint_first_without_repeats() {
+        let mut settings = RomajiSettings::default();
+        settings.cycle_hint();
+        assert_eq!(settings.hint(), RomajiSystem::Kunrei);
+        assert_eq!(settings.spell(&["chiji".into()]), ["tizi", "chiji"]);
+    }
+
+    #[test]
+    fn the_last_system_stays_allowed() {
+        let mut settings = RomajiSettings::default();
+        for system in RomajiSystem::ALL {
+            settings.toggle(system);
+        }
+        assert_eq!(
+            RomajiSystem::ALL.map(|v| settings.is_allowed(v)),
+            [false, false, false, true]
+        );
+        assert_eq!(settings.hint(), RomajiSystem::Ime);
+    }
+}
@@ -0,0 +1,165 @@
+use std::time::Duration;
+
+use crate::typing_session::{Metrics, SessionStats};
+
+/// How long a session runs. Timed and fixed-length sessions start on the first keystroke.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum SessionMode {
+    /// Keeps going, starting the next text of the same kind when one runs out.
+    #[default]
+    Endless,
+    /// Types against the clock.
+    Timed(Duration),
+    /// Ends after this many words.
+    Words(usize),
+    /// Ends at the end of the text, like the last line of a song.
+    FinishText,
+}
+
+const TIME_LIMITS: [u64; 4] = [15, 30, 60, 120];
+const WORD_COUNTS: [usize; 3] = [25, 50, 100];
+
+impl SessionMode {
+    /// The mode after this one, for cycling through them with one button.
+    pub fn next(self) -> Self {
+        match self {
+            SessionMode::Endless => SessionMode::Timed(Duration::from_secs(TIME_LIMITS[0])),
+            SessionMode::Timed(limit) => match TIME_LIMITS.iter().find(|v| **v > limit.as_secs()) {
+                Some(v) => SessionMode::Timed(Duration::from_secs(*v)),
+                None => SessionMode::Words(WORD_COUNTS[0]),
+            },
+            SessionMode::Words(count) => match WORD_COUNTS.iter().find(|v| **v > count) {
+                Some(v) => SessionMode::Words(*v),
+                None => SessionMode::FinishText,
+            },
+            SessionMode::FinishText => SessionMode::Endless,
+        }
+    }
+
+    /// Why the session is over, `elapsed` since its first keystroke with `words` typed, or
+    /// `None` while it goes on.
+    pub fn end_reason(self, elapsed: Option<Duration>, words: usize) -> Option<EndReason> {
+        match self {
+            SessionMode::Timed(limit) => elapsed.filter(|v| *v >= limit).map(|_| EndReason::TimeUp),
+            SessionMode::Words(count) => (words >= count).then_some(EndReason::WordsDone),
+            SessionMode::Endless | SessionMode::FinishText => None,
+        }
+    }
+
+    /// Why the session is over once its text runs out, or `None` if it goes on to another.
+    pub fn text_end(self) -> Option<EndReason> {
+        match self {
+            SessionMode::Endless => None,
+            _ => Some(EndReason::TextDone),
+        }
+    }
+}
+
+impl std::fmt::Display for SessionMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SessionMode::Endless => write!(f, "Endless"),
+            SessionMode::Timed(limit) => write!(f, "{}s Time Attack", limit.as_secs()),
+            SessionMode::Words(count) => write!(f, "{} Words", count),
+            SessionMode::FinishText => write!(f, "Finish The Text"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EndReason {
+    TimeUp,
+    WordsDone,
+    TextDone,
+}
+
+impl std::fmt::Display for EndReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EndReason::TimeUp => write!(f, "Time's up"),
+            EndReason::WordsDone => write!(f, "All words typed"),
+            EndReason::TextDone => write!(f, "End of the text"),
+        }
+    }
+}
+
+/// How a timed or fixed-length session went, for the results screen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Results {
+    pub mode: SessionMode,
+    pub reason: EndReason,
+    pub title: Option<String>,
+    pub stats: SessionStats,
+    pub metrics: Metrics,
+    pub words: usize,
+    pub score: usize,
+    pub max_combo: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn time_attack_ends_when_the_time_is_up() {
+        let mode = SessionMode::Timed(MINUTE);
+        assert_eq!(mode.end_reason(None, 500), None);
+        assert_eq!(
+            mode.end_reason(Some(MINUTE - Duration::from_millis(1)), 500),
+            None
+        );
+        assert_eq!(mode.end_reason(Some(MINUTE), 0), Some(EndReason::TimeUp));
+        assert_eq!(mode.text_end(), Some(EndReason::TextDone));
+    }
+
+    #[test]
+    fn word_count_ends_on_the_last_word() {
+        let mode = SessionMode::Words(25);
+        assert_eq!(mode.end_reason(Some(MINUTE * 10), 24), None);
+        assert_eq!(mode.end_reason(None, 25), Some(EndReason::WordsDone));
+        assert_eq!(mode.text_end(), Some(EndReason::TextDone));
+    }
+
+    #[test]
+    fn finish_text_only_ends_with_the_text() {
+        let mode = SessionMode::FinishText;
+        assert_eq!(mode.end_reason(Some(MINUTE * 10), 1000), None);
+        assert_eq!(mode.text_end(), Some(EndReason::TextDone));
+    }
+
+    #[test]
+    fn endless_never_ends() {
+        let mode = SessionMode::Endless;
+        assert_eq!(mode.end_reason(Some(MINUTE * 10), 1000), None);
+        assert_eq!(mode.text_end(), None);
+    }
+
+    #[test]
+    fn next_cycles_back_to_endless() {
+        let mut mode = SessionMode::Endless;
+        let mut seen = vec![];
+        loop {
+            mode = mode.next();
+            seen.push(mode.to_string());
+            if mode == SessionMode::Endless {
+                break;
+            }
+        }
+        assert_eq!(
+            seen,
+            [
+                "15s Time Attack",
+                "30s Time Attack",
+                "60s Time Attack",
+                "120s Time Attack",
+                "25 Words",
+                "50 Words",
+                "100 Words",
+                "Finish The Text",
+                "Endless",
+            ]
+        );
+    }
+}
@@ -0,0 +1,489 @@
+use std::{
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+/// How much of each file is read to decide if it's binary or generated.
+const SNIFF_BYTES: usize = 8000;
+
+const GENERATED_MARKERS: [&str; 4] = [
+    "@generated",
+    "do not edit",
+    "auto-generated",
+    "autogenerated",
+];
+const GENERATED_NAMES: [&str; 6] = [
+    "Cargo.lock",
+    "package-lock.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    "poetry.lock",
+    "uv.lock",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileOrder {
+    Random,
+    Sequential,
+}
+
+/// How leading whitespace is loaded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IndentStyle {
+    /// Trim every line, typing the code as a flat stream of statements.
+    Flat,
+    /// Keep the file's own tabs and spaces.
+    Keep,
+    Spaces,
+    Tabs,
+}
+
+/// Whether the typist has to type the indentation of each line.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum IndentMode {
+    Skip,
+    /// Indentation is always loaded as spaces then, since a tab can't be typed into the
+    /// text box.
+    Require,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeSettings {
+    pub indent_style: IndentStyle,
+    pub tab_width: usize,
+    pub indent_mode: IndentMode,
+}
+
+impl Default for CodeSettings {
+    fn default() -> Self {
+        Self {
+            indent_style: IndentStyle::Spaces,
+            tab_width: 4,
+            indent_mode: IndentMode::Skip,
+        }
+    }
+}
+
+impl CodeSettings {
+    /// Rewrites the leading whitespace of `line` in the configured style, or in spaces
+    /// when the typist has to type it.
+    pub fn normalize_indent(&self, line: &str) -> String {
+        let body = line.trim_start();
+        let indent = &line[..line.len() - body.len()];
+        let tab_width = self.tab_width.max(1);
+        let width = indent.chars().fold(0, |width, c| {
+            if c == '\t' {
+                (width / tab_width + 1) * tab_width
+            } else {
+                width + 1
+            }
+        });
+        let style = match (self.indent_style, self.indent_mode) {
+            (IndentStyle::Keep | IndentStyle::Tabs, IndentMode::Require) => IndentStyle::Spaces,
+            (style, _) => style,
+        };
+        match style {
+            IndentStyle::Flat => body.to_string(),
+            IndentStyle::Keep => line.to_string(),
+            IndentStyle::Spaces => " ".repeat(width) + body,
+            IndentStyle::Tabs => {
+                "\t".repeat(width / tab_width) + &" ".repeat(width % tab_width) + body
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceTreeSettings {
+    pub root: PathBuf,
+    /// Comma separated, empty for any extension.
+    pub extensions: String,
+    /// Matched against the path from `root`, or just the file name if it has no `/`.
+    pub glob: String,
+    pub order: FileOrder,
+    pub code: CodeSettings,
+}
+
+impl Default for SourceTreeSettings {
+    fn default() -> Self {
+        Self {
+            root: std::env::current_dir().unwrap_or_default(),
+            extensions: "rs".into(),
+            glob: "".into(),
+            order: FileOrder::Random,
+            code: CodeSettings::default(),
+        }
+    }
+}
+
+impl SourceTreeSettings {
+    /// True if both settings would walk to the same files, whatever the code settings.
+    pub fn walks_like(&self, other: &Self) -> bool {
+        self.root == other.root && self.extensions == other.extensions && self.glob == other.glob
+    }
+
+    pub fn extensions(&self) -> Vec<String> {
+        self.extensions
+            .split(',')
+            .map(|v| v.trim().trim_start_matches('.').to_lowercase())
+            .filter(|v| !v.is_empty())
+            .collect()
+    }
+
+    fn wants(&self, relative: &str) -> bool {
+        let extensions = self.extensions();
+        if !extensions.is_empty() {
+            let extension = Path::new(relative)
+                .extension()
+                .map(|v| v.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            if !extensions.contains(&extension) {
+                return false;
+            }
+        }
+        let glob = self.glob.trim();
+        if glob.is_empty() {
+            true
+        } else if glob.contains('/') {
+            glob_match(glob.trim_start_matches('/'), relative)
+        } else {
+            glob_match(glob, file_name(relative))
+        }
+    }
+}
+
+/// Walks `settings.root` for files worth typing, sorted by path.
+///
+/// `.gitignore` files are honored at every level, and binary or generated files are
+/// skipped along with the `.git` directory.
+pub fn collect_source_files(settings: &SourceTreeSettings) -> io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    let mut ignore = Gitignore::default();
+    walk(settings, &settings.root, "", &mut ignore, &mut files)?;
+    files.sort();
+    Ok(files)
+}
+
+fn walk(
+    settings: &SourceTreeSettings,
+    dir: &Path,
+    relative_dir: &str,
+    ignore: &mut Gitignore,
+    files: &mut Vec<PathBuf>,
+) -> io::Result<()> {
+    let rules_before = ignore.rules.len();
+    if let Ok(source) = fs::read_to_string(dir.join(".gitignore")) {
+        ignore.add(relative_dir, &source);
+    }
+
+    let mut entries: Vec<fs::DirEntry> = fs::read_dir(dir)?.filter_map(Result::ok).collect();
+    entries.sort_by_key(|v| v.file_name());
+    for entry in entries {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let relative = if relative_dir.is_empty() {
+            name.clone()
+        } else {
+            format!("{}/{}", relative_dir, name)
+        };
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            if name == ".git" || ignore.is_ignored(&relative, true) {
+                continue;
+            }
+            // unreadable directories are skipped, not fatal
+            let _ = walk(settings, &entry.path(), &relative, ignore, files);
+        } else if file_type.is_file()
+            && !ignore.is_ignored(&relative, false)
+            && settings.wants(&relative)
+            && !is_generated_name(&name)
+            && is_plain_source(&entry.path())
+        {
+            files.push(entry.path());
+        }
+    }
+
+    ignore.rules.truncate(rules_before);
+    Ok(())
+}
+
+fn file_name(relative: &str) -> &str {
+    relative.rsplit('/').next().unwrap_or(relative)
+}
+
+fn is_generated_name(name: &str) -> bool {
+    GENERATED_NAMES.contains(&name) || name.ends_with(".min.js") || name.ends_with(".min.css")
+}
+
+/// False for binary files and files that say they were generated.
+fn is_plain_source(path: &Path) -> bool {
+    let mut buf = vec![0; SNIFF_BYTES];
+    let read = match fs::File::open(path).and_then(|mut file| file.read(&mut buf)) {
+        Ok(read) => read,
+        Err(_) => return false,
+    };
+    let head = &buf[..read];
+    if head.contains(&0) {
+        return false;
+    }
+    let head = match std::str::from_utf8(head) {
+        Ok(v) => v,
+        // the sniff can cut a character in half, which is still text
+        Err(e) if e.error_len().is_none() => {
+            std::str::from_utf8(&head[..e.valid_up_to()]).unwrap_or_default()
+        }
+        Err(_) => return false,
+    };
+    !head.lines().take(5).any(|line| {
+        let line = line.to_lowercase();
+        GENERATED_MARKERS.iter().any(|marker| line.contains(marker))
+    })
+}
+
+#[derive(Debug, Default)]
+struct Gitignore {
+    rules: Vec<IgnoreRule>,
+}
+
+#[derive(Debug)]
+struct IgnoreRule {
+    /// The directory of the `.gitignore`, relative to the root.
+    base: String,
+    pattern: String,
+    negated: bool,
+    dir_only: bool,
+    /// Patterns with a `/` before the end only match from `base`, not at any depth.
+    anchored: bool,
+}
+
+impl Gitignore {
+    fn add(&mut self, base: &str, source: &str) {
+        for line in source.lines() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (negated, line) = match line.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, line.strip_prefix('\\').unwrap_or(line)),
+            };
+            let (dir_only, line) = match line.strip_suffix('/') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let anchored = line.contains('/');
+            self.rules.push(IgnoreRule {
+                base: base.to_string(),
+                pattern: line.trim_start_matches('/').to_string(),
+                negated,
+                dir_only,
+                anchored,
+            });
+        }
+    }
+
+    /// The last matching rule wins, like git.
+    fn is_ignored(&self, relative: &str, is_dir: bool) -> bool {
+        let mut ignored = false;
+        for rule in &self.rules {
+            if rule.dir_only && !is_dir {
+                continue;
+            }
+            let within_base = if rule.base.is_empty() {
+                Some(relative)
+            } else {
+                relative
+                    .strip_prefix(rule.base.as_str())
+                    .and_then(|v| v.strip_prefix('/'))
+            };
+            let Some(within_base) = within_base else {
+                continue;
+            };
+            let matched = if rule.anchored {
+                glob_match(&rule.pattern, within_base)
+            } else {
+                glob_match(&rule.pattern, file_name(within_base))
+            };
+            if matched {
+                ignored = !rule.negated;
+            }
+        }
+        ignored
+    }
+}
+
+/// Matches `*`, `?`, `[abc]` and `**` globs, where only `**` crosses a `/`.
+pub fn glob_match(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let path: Vec<char> = path.chars().collect();
+    glob_match_from(&pattern, &path)
+}
+
+fn glob_match_from(pattern: &[char], path: &[char]) -> bool {
+    match pattern.first() {
+        None => path.is_empty(),
+        Some('*') if pattern.get(1) == Some(&'*') => {
+            // `**/` may also match no directories at all
+            let rest = &pattern[2..];
+            let rest_after_slash = rest.strip_prefix(&['/']).unwrap_or(rest);
+            (0..=path.len()).any(|i| {
+                glob_match_from(rest, &path[i..]) || glob_match_from(rest_after_slash, &path[i..])
+            })
+        }
+        Some('*') => {
+            let rest = &pattern[1..];
+            for i in 0..=path.len() {
+                if glob_match_from(rest, &path[i..]) {
+                    return true;
+                }
+                if path.get(i) == Some(&'/') {
+                    break;
+                }
+            }
+            false
+        }
+        Some('?') => {
+            matches!(path.first(), Some(c) if *c != '/')
+                && glob_match_from(&pattern[1..], &path[1..])
+        }
+        Some('[') => {
+            let Some(close) = pattern.iter().skip(1).position(|c| *c == ']') else {
+                return path.first() == Some(&'[') && glob_match_from(&pattern[1..], &path[1..]);
+            };
+            let class = &pattern[1..close + 1];
+            let Some(c) = path.first() else {
+                return false;
+            };
+            let (negated, class) = match class.first() {
+                Some('!' | '^') => (true, &class[1..]),
+                _ => (false, class),
+            };
+            let mut in_class = false;
+            let mut i = 0;
+            while i < class.len() {
+                if class.get(i + 1) == Some(&'-') && i + 2 < class.len() {
+                    in_class |= class[i] <= *c && *c <= class[i + 2];
+                    i += 3;
+                } else {
+                    in_class |= class[i] == *c;
+                    i += 1;
+                }
+            }
+            in_class != negated && glob_match_from(&pattern[close + 2..], &path[1..])
+        }
+        Some(p) => path.first() == Some(p) && glob_match_from(&pattern[1..], &path[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code(indent_style: IndentStyle, indent_mode: IndentMode) -> CodeSettings {
+        CodeSettings {
+            indent_style,
+            tab_width: 4,
+            indent_mode,
+        }
+    }
+
+    #[test]
+    fn required_indent_is_loaded_as_spaces() {
+        for style in [IndentStyle::Tabs, IndentStyle::Keep, IndentStyle::Spaces] {
+            let code = code(style, IndentMode::Require);
+            assert_eq!(code.normalize_indent("\t\tx"), "        x");
+            assert_eq!(code.normalize_indent("\t  x"), "      x");
+        }
+    }
+
+    #[test]
+    fn skipped_indent_keeps_its_style() {
+        assert_eq!(
+            code(IndentStyle::Tabs, IndentMode::Skip).normalize_indent("      x"),
+            "\t  x"
+        );
+        assert_eq!(
+            code(IndentStyle::Keep, IndentMode::Skip).normalize_indent("\t x"),
+            "\t x"
+        );
+    }
+
+    fn gitignore(source: &str) -> Gitignore {
+        let mut gitignore = Gitignore::default();
+        gitignore.add("", source);
+        gitignore
+    }
+
+    #[test]
+    fn double_star_crosses_directories() {
+        assert!(glob_match("**/test/*.rs", "test/a.rs"));
+        assert!(glob_match("**/test/*.rs", "a/b/test/c.rs"));
+        assert!(glob_match("src/**/mod.rs", "src/mod.rs"));
+        assert!(glob_match("src/**/mod.rs", "src/a/b/mod.rs"));
+        assert!(!glob_match("src/**/mod.rs", "lib/a/mod.rs"));
+    }
+
+    #[test]
+    fn single_star_stops_at_a_slash() {
+        assert!(glob_match("src/*.rs", "src/main.rs"));
+        assert!(!glob_match("src/*.rs", "src/a/main.rs"));
+        assert!(glob_match("*", "main.rs"));
+        assert!(!glob_match("*", "src/main.rs"));
+    }
+
+    #[test]
+    fn question_mark_is_one_character_but_not_a_slash() {
+        assert!(glob_match("a?c", "abc"));
+        assert!(!glob_match("a?c", "ac"));
+        assert!(!glob_match("a?c", "a/c"));
+    }
+
+    #[test]
+    fn character_classes_take_ranges_and_negation() {
+        assert!(glob_match("[abc].rs", "b.rs"));
+        assert!(!glob_match("[abc].rs", "d.rs"));
+        assert!(glob_match("[a-c]1", "b1"));
+        assert!(!glob_match("[a-c]1", "d1"));
+        assert!(glob_match("[!a]x", "bx"));
+        assert!(!glob_match("[!a]x", "ax"));
+    }
+
+    #[test]
+    fn negation_after_a_broader_rule_unignores() {
+        let gitignore = gitignore("*.log\n!keep.log");
+        assert!(gitignore.is_ignored("debug.log", false));
+        assert!(!gitignore.is_ignored("keep.log", false));
+        assert!(!gitignore.is_ignored("logs/keep.log", false));
+        // the last matching rule wins, so a later broad rule ignores it again
+        let gitignore = self::gitignore("!keep.log\n*.log");
+        assert!(gitignore.is_ignored("keep.log", false));
+    }
+
+    #[test]
+    fn leading_slash_anchors_to_the_gitignore() {
+        let anchored = gitignore("/build");
+        assert!(anchored.is_ignored("build", true));
+        assert!(!anchored.is_ignored("src/build", true));
+        let anywhere = gitignore("build");
+        assert!(anywhere.is_ignored("build", true));
+        assert!(anywhere.is_ignored("src/build", true));
+    }
+
+    #[test]
+    fn trailing_slash_only_matches_directories() {
+        let gitignore = gitignore("out/");
+        assert!(gitignore.is_ignored("out", true));
+        assert!(gitignore.is_ignored("src/out", true));
+        assert!(!gitignore.is_ignored("out", false));
+    }
+
+    #[test]
+    fn nested_gitignores_apply_below_their_directory() {
+        let mut gitignore = Gitignore::default();
+        gitignore.add("src", "*.tmp");
+        assert!(gitignore.is_ignored("src/a.tmp", false));
+        assert!(!gitignore.is_ignored("a.tmp", false));
+    }
+}
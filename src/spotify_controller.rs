@@ -1,4 +1,7 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
 
 use rspotify::{AuthCodeSpotify, Credentials, OAuth, prelude::*};
 
@@ -15,6 +18,18 @@ pub struct Song {
     pub id: String,
     // artists:Vec<Artist>,
     pub artist: String,
+    pub album: Option<String>,
+    pub duration_ms: Option<i64>,
+}
+
+/// What the player was doing, as Spotify reported it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaybackState {
+    pub track_id: Option<String>,
+    pub progress: Duration,
+    pub is_playing: bool,
+    /// About when Spotify was at `progress`, halfway through the request.
+    pub at: Instant,
 }
 
 // pub struct Artist {
@@ -29,71 +44,99 @@ impl Song {
                 name: "Yumeyume".into(),
                 id: "05ReuhxWC85vxG530BGty7".into(),
                 artist: "DECO*27".into(),
+                album: None,
+                duration_ms: None,
             },
             Song {
                 name: "Crazy for you".into(),
                 id: "0xIW9Iex1ziifoFcRL1JVS".into(),
                 artist: "焼塩檸檬".into(),
+                album: None,
+                duration_ms: None,
             },
             Song {
                 name: "仮死化".into(),
                 id: "4sVdacv8Qflef5SDiYXUpg".into(),
                 artist: "Vivid BAD SQUAD".into(),
+                album: None,
+                duration_ms: None,
             },
             Song {
                 name: "メリュー".into(),
                 id: "6Tl3V1vOgah4pAwXUGeuI3".into(),
                 artist: "25時".into(),
+                album: None,
+                duration_ms: None,
             },
             Song {
                 name: "Hai Yorokonde".into(),
                 id: "6woV8uWxn7rcLZxJKYruS1".into(),
                 artist: "Kocchi no Kento".into(),
+                album: None,
+                duration_ms: None,
             },
             Song {
                 name: "Golden".into(),
                 id: "1CPZ5BxNNd0n0nF4Orb9JS".into(),
                 artist: "HUNTR/X".into(),
+                album: None,
+                duration_ms: None,
             },
             Song {
                 name: "Mr. Brightside".into(),
                 id: "003vvx7Niy0yvhvHt4a68B".into(),
                 artist: "The Killers".into(),
+                album: None,
+                duration_ms: None,
             },
             Song {
                 name: "100 bad days".into(),
                 id: "4rnyUV17cSZGsz18xJNdjL".into(),
                 artist: "AJR".into(),
+                album: None,
+                duration_ms: None,
             },
             Song {
                 name: "115".into(),
                 id: "725NSbIej5lP3GfhLC7So3".into(),
                 artist: "Kevin Sherwood".into(),
+                album: None,
+                duration_ms: None,
             },
             Song {
                 name: "Nobody".into(),
                 id: "3SiVMpHxTS1gspWzRZE50S".into(),
                 artist: "OneRepublic".into(),
+                album: None,
+                duration_ms: None,
             },
             Song {
                 name: "Timber".into(),
                 id: "3cHyrEgdyYRjgJKSOiOtcS".into(),
                 artist: "Pitbull".into(),
+                album: None,
+                duration_ms: None,
             },
             Song {
                 name: "I Want It That Way".into(),
                 id: "47BBI51FKFwOMlIiX6m8ya".into(),
                 artist: "Backstreet Boys".into(),
+                album: None,
+                duration_ms: None,
             },
             Song {
                 name: "Shivers".into(),
                 id: "78AjaULHLHTUs2UhaTCM8N".into(),
                 artist: "Ed Sheeran".into(),
+                album: None,
+                duration_ms: None,
             },
             Song {
                 name: "It Ends Tonight".into(),
                 id: "1FMHNVeJ9s1x1l1WlaRs2I".into(),
                 artist: "The All-American Rejects".into(),
+                album: None,
+                duration_ms: None,
             },
         ]
     }
@@ -130,7 +173,10 @@ impl SpotifyController {
     }
 
     pub async fn init_from_env(oauth: OAuth) -> anyhow::Result<Self> {
-        Self::init(Credentials::from_env().unwrap(), oauth).await
+        let creds = Credentials::from_env().ok_or(anyhow::anyhow!(
+            "RSPOTIFY_CLIENT_ID is not set in the environment"
+        ))?;
+        Self::init(creds, oauth).await
     }
 
     pub fn set_device_id(&mut self, new_id: String) {
@@ -175,10 +221,11 @@ impl SpotifyController {
             }
         };
         let client = reqwest::Client::new();
-        let url = format!("https://api.spotify.com/v1/search?type=track&q={}", query);
+        let url = "https://api.spotify.com/v1/search";
 
         let res = match client
             .get(url)
+            .query(&[("type", "track"), ("q", query.as_str())])
             .bearer_auth(access_token)
             .header("Content-Length", 0)
             .send()
@@ -195,7 +242,9 @@ impl SpotifyController {
         parse_search_response(res).await
     }
 
-    pub async fn play(&mut self) -> anyhow::Result<()> {
+    /// Starts playback, from the top of the song set with `set_song_id` if there is one and
+    /// from where it was paused otherwise. Returns whether a new song was started.
+    pub async fn play(&mut self) -> anyhow::Result<bool> {
         let access_token = self.get_access_token().await?;
         let client = reqwest::Client::new();
         let url = "https://api.spotify.com/v1/me/player";
@@ -230,8 +279,33 @@ impl SpotifyController {
                 .await?;
             println!("2nd Response {:#?}", res.status());
         }
-        self.song_id = None;
-        Ok(())
+        Ok(self.song_id.take().is_some())
+    }
+
+    /// Where the player is in the current song, or `None` if nothing is playing.
+    pub async fn playback_state(&self) -> anyhow::Result<Option<PlaybackState>> {
+        let access_token = self.get_access_token().await?;
+        let client = reqwest::Client::new();
+        let url = "https://api.spotify.com/v1/me/player/currently-playing";
+
+        let sent = Instant::now();
+        let res = client
+            .get(url)
+            .bearer_auth(access_token)
+            .header("Content-Length", 0)
+            .send()
+            .await?;
+        let at = sent + sent.elapsed() / 2;
+        if res.status() == reqwest::StatusCode::NO_CONTENT {
+            return Ok(None);
+        }
+        let playing = res.error_for_status()?.json::<CurrentlyPlaying>().await?;
+        Ok(Some(PlaybackState {
+            track_id: playing.item.and_then(|v| v.id),
+            progress: Duration::from_millis(playing.progress_ms.unwrap_or(0).max(0) as u64),
+            is_playing: playing.is_playing,
+            at,
+        }))
     }
 
     pub async fn pause(&self) -> anyhow::Result<()> {
@@ -310,6 +384,19 @@ async fn parse_devices_response(res: reqwest::Response) -> Vec<(String, String)>
         .collect()
 }
 
+#[derive(Debug, serde::Deserialize)]
+struct CurrentlyPlaying {
+    progress_ms: Option<i64>,
+    is_playing: bool,
+    item: Option<PlayingItem>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct PlayingItem {
+    /// Local files and some podcasts have none.
+    id: Option<String>,
+}
+
 #[derive(Debug, serde::Deserialize)]
 struct SearchResults {
     #[serde(rename = "href")]
@@ -331,7 +418,7 @@ struct SearchResults {
 #[derive(Debug, serde::Deserialize)]
 struct SearchResultItem {
     #[serde(rename = "album")]
-    _album: Album,
+    album: Album,
     #[serde(rename = "artists")]
     _artists: Vec<Artist>,
     #[serde(rename = "available_markets")]
@@ -339,7 +426,7 @@ struct SearchResultItem {
     #[serde(rename = "disc_number")]
     _disc_number: i32,
     #[serde(rename = "duration_ms")]
-    _duration: i64,
+    duration_ms: i64,
     #[serde(rename = "explicit")]
     _explicit: bool,
     #[serde(rename = "external_ids")]
@@ -387,7 +474,7 @@ struct Album {
     #[serde(rename = "is_playable")]
     _is_playable: bool,
     #[serde(rename = "name")]
-    _name: String,
+    name: String,
     #[serde(rename = "release_date")]
     _release_date: String,
     #[serde(rename = "release_date_precision")]
@@ -453,11 +540,13 @@ async fn parse_search_response(res: reqwest::Response) -> Option<Vec<Song>> {
             .items
             .iter()
             .enumerate()
-            .filter(|(i, _)| *i <= 10 as usize)
+            .filter(|(i, _)| *i <= 10)
             .map(|(_, v)| Song {
                 name: v.name.clone(),
                 id: v.id.clone(),
                 artist: v._artists[0].name.clone(),
+                album: Some(v.album.name.clone()),
+                duration_ms: Some(v.duration_ms),
             })
             .collect(),
     )
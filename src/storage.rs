@@ -0,0 +1,42 @@
+use std::{
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::anyhow;
+
+const APP_DIR: &str = "iced_musical_typing";
+
+/// Where everything we keep between runs lives: `$XDG_DATA_HOME/iced_musical_typing`,
+/// or `~/.local/share/iced_musical_typing` when that isn't set. Fails when neither
+/// `XDG_DATA_HOME` nor `HOME` is set, rather than scattering files wherever we were run.
+pub fn data_dir() -> anyhow::Result<PathBuf> {
+    let base = match std::env::var_os("XDG_DATA_HOME") {
+        Some(v) if !v.is_empty() => PathBuf::from(v),
+        _ => match std::env::var_os("HOME") {
+            Some(home) if !home.is_empty() => PathBuf::from(home).join(".local/share"),
+            _ => return Err(anyhow!("neither XDG_DATA_HOME nor HOME is set")),
+        },
+    };
+    Ok(base.join(APP_DIR))
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|v| v.as_secs())
+        .unwrap_or(0)
+}
+
+/// Turns a key into something safe to use as a file name.
+pub fn file_stem(key: &str) -> String {
+    key.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
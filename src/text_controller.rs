@@ -1,33 +1,63 @@
-use std::{
-    fs::File,
-    io::{self, BufRead},
-    path::PathBuf,
-    thread,
-};
+use std::{path::PathBuf, sync::Arc, thread};
 
 use crate::TextType;
+use crate::lesson::Lesson;
+use crate::lrc::{self, SyncWindow};
+use crate::lyrics::{self, CandidateSummary, LyricsCandidate, LyricsQuery};
+use crate::lyrics_cache::{self, CacheListing, LyricsCache};
+use crate::lyrics_provider::{self, LyricsProvider};
+use crate::rng::Rng;
+use crate::source_tree::{self, CodeSettings, FileOrder, SourceTreeSettings};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct TextController {
     loaded_lyrics: Vec<String>,
-}
-
-impl Default for TextController {
-    fn default() -> Self {
-        Self {
-            loaded_lyrics: vec![],
-        }
-    }
+    // one window per loaded line, empty unless the text is synced
+    loaded_windows: Vec<SyncWindow>,
+    // what is being typed, when there's more to say than the text type
+    loaded_title: Option<String>,
+    // every provider result of the last search, best match first
+    lyrics_candidates: Vec<LyricsCandidate>,
+    last_query: Option<LyricsQuery>,
+    // missing when there's nowhere to keep it
+    cache: Option<LyricsCache>,
+    // asked in order, the first with a good enough match wins
+    providers: Vec<Arc<dyn LyricsProvider>>,
+    source_settings: Option<SourceTreeSettings>,
+    source_files: Vec<PathBuf>,
+    next_source_file: usize,
+    // set while a lesson is loaded, which never runs out of lines
+    lesson: Option<Lesson>,
 }
 
 pub const NUM_LINES: usize = 20;
 
 impl TextController {
     pub async fn init() -> Self {
-        Self::default()
+        let mut text_controller = Self {
+            cache: LyricsCache::open()
+                .map_err(|e| log::error!("lyrics won't be cached: {}", e))
+                .ok(),
+            ..Self::default()
+        };
+        text_controller.set_providers(lyrics_provider::providers_from_env());
+        text_controller.cache = LyricsCache::open()
+            .map_err(|e| log::error!("lyrics won't be cached: {}", e))
+            .ok();
+        text_controller
+    }
+
+    pub fn set_providers(&mut self, providers: Vec<Arc<dyn LyricsProvider>>) {
+        self.providers = providers;
     }
 
     pub async fn fetch_lyrics(&mut self, index: usize) -> Option<Vec<String>> {
+        if let Some(lesson) = &self.lesson
+            && index + NUM_LINES > self.loaded_lyrics.len()
+        {
+            let missing = index + NUM_LINES - self.loaded_lyrics.len();
+            self.loaded_lyrics.extend(lesson.generate_lines(missing));
+        }
         if index > self.loaded_lyrics.len() {
             None
         } else {
@@ -40,110 +70,306 @@ impl TextController {
         }
     }
 
-    pub async fn load_lyrics(&mut self, mode: TextType, song_name: Option<String>) -> bool {
+    pub async fn fetch_windows(&mut self, index: usize) -> Vec<SyncWindow> {
+        if index >= self.loaded_windows.len() {
+            vec![]
+        } else {
+            let index_end = (index + NUM_LINES).min(self.loaded_windows.len());
+            self.loaded_windows[index..index_end].to_vec()
+        }
+    }
+
+    pub fn loaded_title(&self) -> Option<String> {
+        self.loaded_title.clone()
+    }
+
+    pub fn lyrics_candidates(&self) -> Vec<CandidateSummary> {
+        self.lyrics_candidates.iter().map(|v| v.summary()).collect()
+    }
+
+    pub async fn load_lyrics(&mut self, mode: TextType, song: Option<LyricsQuery>) -> bool {
+        let synced = matches!(mode, TextType::SyncedLrclib);
+        self.lyrics_candidates = vec![];
+        self.lesson = None;
         match mode {
-            TextType::LRCLIB => match song_name {
-                Some(query) => match get_lrclib_lyrics(query).await {
-                    Ok(v) => {
-                        if v.is_empty() {
-                            false
-                        } else {
-                            self.loaded_lyrics = v;
-                            true
-                        }
-                    }
-                    Err(_) => false,
-                },
+            TextType::Lrclib | TextType::SyncedLrclib => match song {
+                Some(query) => self.load_provider_lyrics(query, synced).await,
                 None => false,
             },
-            // TextType::Github => todo!(),
-            TextType::ThisProject => {
-                match load_new_lines(format!("{}/src/main.rs", env!("CARGO_MANIFEST_DIR"))).await {
-                    Ok(v) => {
-                        if v.is_empty() {
-                            false
-                        } else {
-                            self.loaded_lyrics = v;
-                            true
-                        }
+            TextType::LocalFile(path) => match load_lyrics_file(path.clone()).await {
+                Ok(file) => {
+                    if file.lyrics.is_empty() {
+                        false
+                    } else {
+                        self.loaded_title = match (file.title, file.artist) {
+                            (Some(title), Some(artist)) => Some(title + " by " + &artist),
+                            (Some(title), None) => Some(title),
+                            _ => path.file_name().map(|v| v.to_string_lossy().to_string()),
+                        };
+                        self.loaded_lyrics = file.lyrics;
+                        self.loaded_windows = file.windows;
+                        true
                     }
-                    Err(_) => false,
                 }
+                Err(e) => {
+                    log::error!("could not load lyrics file: {}", e);
+                    false
+                }
+            },
+            // TextType::Github => todo!(),
+            TextType::SourceTree(settings) => self.load_source_file(settings).await,
+            TextType::Lesson(lesson) => {
+                self.loaded_lyrics = lesson.generate_lines(NUM_LINES);
+                self.loaded_windows = vec![];
+                self.lesson = Some(lesson);
+                self.loaded_title = self.lesson_title();
+                true
             }
         }
     }
-}
 
-async fn load_new_lines(file_name: impl Into<PathBuf>) -> anyhow::Result<Vec<String>> {
-    let file_path = file_name.into();
+    /// Swaps in a lesson built from newer key stats, so the lines still to come adapt to
+    /// how the typist is doing. Does nothing unless a lesson is loaded.
+    pub fn adapt_lesson(&mut self, lesson: Lesson) {
+        if self.lesson.is_some() {
+            self.lesson = Some(lesson);
+            self.loaded_title = self.lesson_title();
+        }
+    }
 
-    let line_handle = thread::spawn(move || -> Result<Vec<String>, io::Error> {
-        let file = File::open(file_path)?;
-        let reader = io::BufReader::new(file);
-        let lines: Vec<String> = reader
-            .lines()
-            .filter_map(Result::ok)
-            .filter(|v| !v.is_empty())
-            .map(|val| {
-                let mut v = val.trim().to_string();
-                v.push(' ');
-                v
-            })
-            .collect();
-        Ok(lines.clone())
-    });
-
-    if let Ok(result) = line_handle.join() {
-        Ok(result?)
-    } else {
-        Err(anyhow::anyhow!("read file thread panicked"))
+    fn lesson_title(&self) -> Option<String> {
+        self.lesson
+            .as_ref()
+            .map(|v| format!("Lesson on {}, focusing on {}", v.letters(), v.focus()))
+    }
+
+    /// Asks each provider in turn and loads the best ranked result that has the lyrics we
+    /// need.
+    ///
+    /// A provider whose best match scores below [`lyrics::GOOD_MATCH`] passes to the next
+    /// one, but if nobody has a good match the best of the rest is still used. Songs in
+    /// the cache are loaded from disk without asking any provider at all, until
+    /// [`Self::search_providers`] is asked for.
+    async fn load_provider_lyrics(&mut self, query: LyricsQuery, synced: bool) -> bool {
+        if let Some(candidate) = self.cache.as_ref().and_then(|v| v.get(&query))
+            && candidate.lyrics(synced).is_some()
+        {
+            self.lyrics_candidates = vec![candidate];
+            self.last_query = Some(query);
+            return self.select_candidate(0, synced);
+        }
+        self.lyrics_candidates = self.ranked_candidates(&query, synced).await;
+        self.last_query = Some(query);
+        match (0..self.lyrics_candidates.len())
+            .find(|i| self.lyrics_candidates[*i].lyrics(synced).is_some())
+        {
+            Some(index) => self.select_candidate(index, synced),
+            None => false,
+        }
+    }
+
+    /// Asks the providers about a song that was loaded from the cache, listing what they
+    /// have after the cached pick, which stays loaded.
+    pub async fn search_providers(&mut self, synced: bool) {
+        let Some(query) = self.last_query.clone() else {
+            return;
+        };
+        let cached = self
+            .lyrics_candidates
+            .iter()
+            .filter(|v| v.provider == lyrics_cache::CACHE_PROVIDER)
+            .cloned();
+        let mut candidates: Vec<LyricsCandidate> = cached.collect();
+        candidates.extend(self.ranked_candidates(&query, synced).await);
+        self.lyrics_candidates = candidates;
+    }
+
+    /// What the providers have for `query`, best match first, stopping at the first
+    /// provider with a good match.
+    async fn ranked_candidates(&self, query: &LyricsQuery, synced: bool) -> Vec<LyricsCandidate> {
+        let mut candidates = vec![];
+        for provider in &self.providers {
+            match provider.candidates(query).await {
+                Ok(v) => candidates.extend(v),
+                Err(e) => {
+                    log::error!("could not search {}: {}", provider.name(), e);
+                    continue;
+                }
+            }
+            let ranked = lyrics::rank_candidates(query, candidates.clone());
+            if ranked
+                .iter()
+                .any(|v| v.score >= lyrics::GOOD_MATCH && v.lyrics(synced).is_some())
+            {
+                break;
+            }
+        }
+        lyrics::rank_candidates(query, candidates)
+    }
+
+    /// Loads the lyrics of another candidate from the last search, if the pick was wrong.
+    pub fn select_candidate(&mut self, index: usize, synced: bool) -> bool {
+        let Some(candidate) = self.lyrics_candidates.get(index) else {
+            return false;
+        };
+        match candidate.lyrics(synced) {
+            Some((lyrics, windows)) if !lyrics.is_empty() => {
+                if let (Some(cache), Some(query)) = (&self.cache, &self.last_query) {
+                    cache.put(query, candidate);
+                }
+                self.loaded_title = Some(candidate.track.clone() + " by " + &candidate.artist);
+                self.loaded_lyrics = lyrics;
+                self.loaded_windows = windows;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn cache_listing(&self) -> CacheListing {
+        self.cache.as_ref().map(|v| v.listing()).unwrap_or_default()
+    }
+
+    pub fn remove_cached(&mut self, key: &str) {
+        if let Some(cache) = &self.cache {
+            cache.remove(key);
+        }
+    }
+
+    pub fn clear_cache(&mut self) {
+        if let Some(cache) = &self.cache {
+            cache.clear();
+        }
+    }
+
+    pub fn set_cache_limit(&mut self, max_bytes: u64) {
+        if let Some(cache) = &mut self.cache {
+            cache.set_max_bytes(max_bytes);
+        }
+    }
+
+    /// Loads the next file from the source tree, walking it again if the settings changed.
+    async fn load_source_file(&mut self, settings: SourceTreeSettings) -> bool {
+        if !self
+            .source_settings
+            .as_ref()
+            .is_some_and(|v| v.walks_like(&settings))
+        {
+            let walk_settings = settings.clone();
+            let walk_handle =
+                thread::spawn(move || source_tree::collect_source_files(&walk_settings));
+            self.source_files = match walk_handle.join() {
+                Ok(Ok(files)) => files,
+                Ok(Err(e)) => {
+                    log::error!("could not read source tree: {}", e);
+                    return false;
+                }
+                Err(_) => {
+                    log::error!("source tree thread panicked");
+                    return false;
+                }
+            };
+            self.next_source_file = 0;
+            self.source_settings = Some(settings.clone());
+        }
+
+        // files can shrink to nothing between walks, so try each one at most once
+        for _ in 0..self.source_files.len() {
+            let index = match settings.order {
+                FileOrder::Random => Rng::from_clock().below(self.source_files.len()),
+                FileOrder::Sequential => self.next_source_file % self.source_files.len(),
+            };
+            self.next_source_file = index + 1;
+            let path = self.source_files[index].clone();
+            if let Ok(v) = load_code_lines(path.clone(), settings.code.clone()).await
+                && !v.is_empty()
+            {
+                self.loaded_lyrics = v;
+                self.loaded_windows = vec![];
+                self.loaded_title = Some(
+                    path.strip_prefix(&settings.root)
+                        .unwrap_or(&path)
+                        .display()
+                        .to_string(),
+                );
+                return true;
+            }
+        }
+        log::warn!("no source files to type in {}", settings.root.display());
+        false
     }
 }
 
-#[derive(Debug, serde::Deserialize)]
-
-struct LrclibObj {
-    #[serde(rename = "id")]
-    _id: i32,
-    #[serde(rename = "name")]
-    _name: String,
-    #[serde(rename = "trackName")]
-    _track_name: String,
-    #[serde(rename = "artistName")]
-    _artist_name: String,
-    #[serde(rename = "albumName")]
-    _album_name: String,
-    #[serde(rename = "duration")]
-    _duration: f32,
-    #[serde(rename = "instrumental")]
-    _instrumental: bool,
-    #[serde(rename = "plainLyrics")]
-    plain_lyrics: Option<String>,
-    #[serde(rename = "syncedLyrics")]
-    _synced_lyrics: Option<String>,
+/// Loads source code keeping its indentation, unless the settings ask for flat lines.
+///
+/// Blank lines are dropped since there is nothing to type on them.
+async fn load_code_lines(
+    file_name: impl Into<PathBuf>,
+    code: CodeSettings,
+) -> anyhow::Result<Vec<String>> {
+    let source = read_file(file_name).await?;
+    Ok(source
+        .lines()
+        .filter(|v| !v.trim().is_empty())
+        .map(|v| code.normalize_indent(v.trim_end()) + " ")
+        .collect())
 }
 
-async fn get_lrclib_lyrics(query: String) -> anyhow::Result<Vec<String>> {
-    let client = reqwest::Client::new();
-    let url = "https://lrclib.net/api/search?q=".to_owned() + query.as_str();
+async fn read_file(file_name: impl Into<PathBuf>) -> anyhow::Result<String> {
+    let file_path = file_name.into();
 
-    let res: reqwest::Response = client.get(url).header("Content-Length", 0).send().await?;
+    let read_handle = thread::spawn(move || std::fs::read_to_string(file_path));
 
-    println!("LRCLIB Response {:#?}", res.status());
-    // get plainLyrics
-    let plain_lyrics = parse_lrclib_response(res).await.unwrap(); //?;//TODO
+    if let Ok(result) = read_handle.join() {
+        Ok(result?)
+    } else {
+        Err(anyhow::anyhow!("read file thread panicked"))
+    }
+}
 
-    Ok(plain_lyrics
-        .split('\n')
-        .filter(|v| !v.is_empty())
-        .map(|v| v.to_owned() + " ")
-        .collect())
+/// Loads a `.lrc` file as synced lyrics, or any other file as plain lyrics.
+///
+/// An `.lrc` file without any timestamps is read as plain lyrics too.
+/// A lyrics file as it was loaded, with the song named by its `[ti:]` and `[ar:]` tags.
+struct LyricsFile {
+    lyrics: Vec<String>,
+    windows: Vec<SyncWindow>,
+    title: Option<String>,
+    artist: Option<String>,
 }
 
-async fn parse_lrclib_response(res: reqwest::Response) -> anyhow::Result<String> {
-    let map = res.json::<Vec<LrclibObj>>().await.unwrap(); //?;//TODO
-    Ok(map[0]
-        .plain_lyrics
-        .clone()
-        .ok_or(anyhow::anyhow!("Plain Lyrics null"))?)
+async fn load_lyrics_file(path: PathBuf) -> anyhow::Result<LyricsFile> {
+    let is_lrc = path
+        .extension()
+        .is_some_and(|v| v.eq_ignore_ascii_case("lrc"));
+    let source = read_file(path).await?;
+    let lrc_file = if is_lrc {
+        lrc::LrcFile::parse(&source)
+    } else {
+        lrc::LrcFile::default()
+    };
+    let title = lrc_file.title().map(String::from);
+    let artist = lrc_file.artist().map(String::from);
+    if !lrc_file.lines.is_empty() {
+        let (lyrics, windows) = lrc::into_synced_lyrics(&lrc_file.lines);
+        return Ok(LyricsFile {
+            lyrics,
+            windows,
+            title,
+            artist,
+        });
+    }
+    Ok(LyricsFile {
+        lyrics: source
+            .lines()
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+            // skip LRC tags like [ar:] in files that have no timestamps
+            .filter(|v| !(is_lrc && v.starts_with('[') && v.ends_with(']')))
+            .map(|v| v.to_owned() + " ")
+            .collect(),
+        windows: vec![],
+        title,
+        artist,
+    })
 }
@@ -0,0 +1,648 @@
+use std::time::{Duration, Instant};
+
+use crate::char_controller::{CharController, RomajiSegment};
+use crate::key_stats::KeyStats;
+use crate::romanization::RomajiSettings;
+use crate::source_tree::IndentMode;
+
+/// Characters per word, by the usual typing test convention.
+const WORD_LENGTH: f32 = 5.0;
+/// Points taken off a line for each wrong keystroke.
+const ERROR_PENALTY: usize = 2;
+
+/// How mistakes are handled while typing a line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Strictness {
+    /// Wrong keys are refused, so nothing moves on until the right one is typed.
+    #[default]
+    StopOnError,
+    /// Wrong keys are kept, and a line can be finished with mistakes still in it.
+    ContinueThroughErrors,
+    /// Like continuing through errors, but nothing typed can be taken back.
+    NoBackspace,
+}
+
+impl Strictness {
+    /// Points for a finished line under this mode's rules.
+    ///
+    /// Stopping on errors scores a point per character less a penalty per wrong key.
+    /// Typing through errors only scores the characters that ended up right, mistakes left
+    /// in the line cost the penalty on top and fixed ones cost a point each. Without
+    /// backspace every right character is worth two points, since nothing can be fixed.
+    pub fn points(self, line: &CompletedLine) -> usize {
+        let chars = line.text.trim().chars().count();
+        let right = chars.saturating_sub(line.uncorrected);
+        match self {
+            Strictness::StopOnError => chars.saturating_sub(line.errors * ERROR_PENALTY),
+            Strictness::ContinueThroughErrors => right
+                .saturating_sub(line.uncorrected * ERROR_PENALTY)
+                .saturating_sub(line.errors.saturating_sub(line.uncorrected)),
+            Strictness::NoBackspace => (right * 2).saturating_sub(line.uncorrected * ERROR_PENALTY),
+        }
+    }
+}
+
+/// A line the typist finished.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompletedLine {
+    pub text: String,
+    /// Characters typed on the line, including ones that were deleted again.
+    pub keystrokes: usize,
+    /// Keystrokes that took the input off track, whether or not they were fixed.
+    pub errors: usize,
+    /// Characters still wrong when the line was finished.
+    pub uncorrected: usize,
+    /// From the first keystroke on the line to finishing it.
+    pub duration: Duration,
+    pub strictness: Strictness,
+}
+
+impl CompletedLine {
+    pub fn correct(&self) -> usize {
+        self.keystrokes - self.errors
+    }
+
+    pub fn metrics(&self) -> Metrics {
+        Metrics::new(self.keystrokes, self.correct(), self.duration)
+    }
+
+    pub fn points(&self) -> usize {
+        self.strictness.points(self)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SessionStats {
+    pub lines: usize,
+    pub keystrokes: usize,
+    pub errors: usize,
+    /// Time spent typing, not counting the pauses between lines.
+    pub typing_time: Duration,
+}
+
+/// Typing speed and accuracy over some stretch of typing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Metrics {
+    /// Words per minute from correct keystrokes only.
+    pub wpm: f32,
+    /// Words per minute from every keystroke, mistakes included.
+    pub raw_wpm: f32,
+    /// Correct characters per minute.
+    pub cpm: f32,
+    /// From 0 to 1.
+    pub accuracy: f32,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            wpm: 0.0,
+            raw_wpm: 0.0,
+            cpm: 0.0,
+            accuracy: 1.0,
+        }
+    }
+}
+
+impl Metrics {
+    pub fn new(keystrokes: usize, correct: usize, elapsed: Duration) -> Self {
+        let accuracy = if keystrokes == 0 {
+            1.0
+        } else {
+            correct as f32 / keystrokes as f32
+        };
+        let minutes = elapsed.as_secs_f32() / 60.0;
+        if minutes <= 0.0 {
+            return Self {
+                accuracy,
+                ..Default::default()
+            };
+        }
+        Self {
+            wpm: correct as f32 / WORD_LENGTH / minutes,
+            raw_wpm: keystrokes as f32 / WORD_LENGTH / minutes,
+            cpm: correct as f32 / minutes,
+            accuracy,
+        }
+    }
+}
+
+/// A marker moving through the text at a steady target speed, for the typist to keep up
+/// with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pace {
+    wpm: f32,
+    // the line the pace set off from, and when
+    line: usize,
+    started: Instant,
+}
+
+impl Pace {
+    pub fn new(wpm: f32, line: usize, started: Instant) -> Self {
+        Self { wpm, line, started }
+    }
+
+    /// Where the pace has got to in `lines` by `now`, as a line and a position in it. It
+    /// waits at the end of the last line once it runs out.
+    pub fn spot(&self, lines: &[String], now: Instant) -> (usize, usize) {
+        let seconds = now.saturating_duration_since(self.started).as_secs_f32();
+        let mut chars = (seconds * self.wpm * WORD_LENGTH / 60.0) as usize;
+        let mut end = (self.line, 0);
+        for (i, line) in lines.iter().enumerate().skip(self.line) {
+            let len = line.chars().count();
+            if chars < len {
+                return (i, chars);
+            }
+            chars -= len;
+            end = (i, len);
+        }
+        end
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputResult {
+    Typing,
+    LineCompleted(CompletedLine),
+}
+
+/// How a character of the line is shown.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CharState {
+    Right,
+    Wrong,
+    Left,
+}
+
+/// The typing of one line at a time, without any UI.
+///
+/// The frontend hands it each line with [`TypingSession::start_line`] and every change of
+/// the input box with [`TypingSession::handle_input`], and reads back where the typist is.
+/// Input is compared with the line character by character, so with a lenient
+/// [`Strictness`] mistakes can sit anywhere in it.
+#[derive(Debug, Clone)]
+pub struct TypingSession {
+    target: String,
+    input: String,
+    indent_mode: IndentMode,
+    strictness: Strictness,
+    // the romaji of the whole line, worked out the first time it's needed with the
+    // settings it was worked out with
+    romaji: Option<(RomajiSettings, Vec<RomajiSegment>)>,
+    // the segment at the typing position
+    char_bonus: Option<RomajiSegment>,
+    // the last key was refused for being wrong
+    blocked: bool,
+    line_keystrokes: usize,
+    line_errors: usize,
+    // the first keystroke on the line
+    line_started: Option<Instant>,
+    completed: Vec<CompletedLine>,
+    // kept across texts, since they're about the typist and not the text
+    keys: KeyStats,
+}
+
+impl Default for TypingSession {
+    fn default() -> Self {
+        Self::new(IndentMode::Skip)
+    }
+}
+
+impl TypingSession {
+    pub fn new(indent_mode: IndentMode) -> Self {
+        Self {
+            target: String::new(),
+            input: String::new(),
+            indent_mode,
+            strictness: Strictness::default(),
+            romaji: None,
+            char_bonus: None,
+            blocked: false,
+            line_keystrokes: 0,
+            line_errors: 0,
+            line_started: None,
+            completed: vec![],
+            keys: KeyStats::default(),
+        }
+    }
+
+    /// Forgets the finished lines, for a new text.
+    pub fn reset(&mut self) {
+        self.completed = vec![];
+        self.line_keystrokes = 0;
+        self.line_errors = 0;
+        self.line_started = None;
+    }
+
+    pub fn set_indent_mode(&mut self, indent_mode: IndentMode) {
+        self.indent_mode = indent_mode;
+    }
+
+    pub fn strictness(&self) -> Strictness {
+        self.strictness
+    }
+
+    pub fn set_strictness(&mut self, strictness: Strictness) {
+        self.strictness = strictness;
+    }
+
+    /// Moves on to a new line, with the input holding its indent if that is skipped.
+    pub fn start_line(&mut self, target: impl Into<String>) {
+        self.target = target.into();
+        self.input = self.line_start_input();
+        self.romaji = None;
+        self.char_bonus = None;
+        self.blocked = false;
+        self.line_keystrokes = 0;
+        self.line_errors = 0;
+        self.line_started = None;
+        self.keys.break_run();
+    }
+
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    pub fn clear_input(&mut self) {
+        self.input = "".into();
+    }
+
+    /// How far into the line the typist is, not counting romaji that is still being typed.
+    pub fn position(&self) -> usize {
+        self.input.chars().count() - self.pending_len()
+    }
+
+    /// Wrong keystrokes on the line so far, including ones already fixed.
+    pub fn line_errors(&self) -> usize {
+        self.line_errors
+    }
+
+    /// The line split into runs of right, wrong and untyped characters.
+    pub fn segments(&self) -> Vec<(CharState, String)> {
+        let typed: Vec<char> = self.input.chars().take(self.position()).collect();
+        let mut segments: Vec<(CharState, String)> = vec![];
+        for (i, c) in self.target.chars().enumerate() {
+            let state = match typed.get(i) {
+                Some(t) if *t == c => CharState::Right,
+                Some(_) => CharState::Wrong,
+                // show where a refused key was meant to go
+                None if self.blocked && i == typed.len() => CharState::Wrong,
+                None => CharState::Left,
+            };
+            match segments.last_mut() {
+                Some((last, run)) if *last == state => run.push(c),
+                _ => segments.push((state, c.to_string())),
+            }
+        }
+        segments
+    }
+
+    pub fn char_bonus(&self) -> Option<(&str, &[String])> {
+        self.char_bonus
+            .as_ref()
+            .map(|v| (v.symbol.as_str(), v.spellings.as_slice()))
+    }
+
+    pub fn key_stats(&self) -> &KeyStats {
+        &self.keys
+    }
+
+    /// Picks up the key stats of earlier runs.
+    pub fn set_key_stats(&mut self, keys: KeyStats) {
+        self.keys = keys;
+    }
+
+    pub fn last_line(&self) -> Option<&CompletedLine> {
+        self.completed.last()
+    }
+
+    /// Totals over the finished lines and the one being typed, as of `now`.
+    pub fn stats(&self, now: Instant) -> SessionStats {
+        let completed = self.completed_stats();
+        SessionStats {
+            keystrokes: completed.keystrokes + self.line_keystrokes,
+            errors: completed.errors + self.line_errors,
+            typing_time: completed.typing_time + self.line_elapsed(now),
+            ..completed
+        }
+    }
+
+    /// Words finished so far: every word of the finished lines, and the words of the line
+    /// being typed whose closing space has been typed.
+    pub fn words_typed(&self) -> usize {
+        let target: Vec<char> = self.target.chars().collect();
+        let current = (1..self.position().min(target.len()))
+            .filter(|i| target[*i] == ' ' && target[i - 1] != ' ')
+            .count();
+        self.completed
+            .iter()
+            .map(|v| v.text.split_whitespace().count())
+            .sum::<usize>()
+            + current
+    }
+
+    /// Totals over the finished lines only.
+    pub fn completed_stats(&self) -> SessionStats {
+        SessionStats {
+            lines: self.completed.len(),
+            keystrokes: self.completed.iter().map(|v| v.keystrokes).sum(),
+            errors: self.completed.iter().map(|v| v.errors).sum(),
+            typing_time: self.completed.iter().map(|v| v.duration).sum(),
+        }
+    }
+
+    pub fn metrics(&self, now: Instant) -> Metrics {
+        let stats = self.stats(now);
+        Metrics::new(
+            stats.keystrokes,
+            stats.keystrokes - stats.errors,
+            stats.typing_time,
+        )
+    }
+
+    pub fn line_metrics(&self, now: Instant) -> Metrics {
+        Metrics::new(
+            self.line_keystrokes,
+            self.line_keystrokes - self.line_errors,
+            self.line_elapsed(now),
+        )
+    }
+
+    fn line_elapsed(&self, now: Instant) -> Duration {
+        self.line_started
+            .map(|start| now.saturating_duration_since(start))
+            .unwrap_or_default()
+    }
+
+    /// The romaji at the end of the input that could still become the symbol there.
+    fn pending_len(&self) -> usize {
+        let Some(bonus) = &self.char_bonus else {
+            return 0;
+        };
+        let len = self.input.chars().count();
+        if len <= bonus.at {
+            return 0;
+        }
+        let tail: String = self.input.chars().skip(bonus.at).collect();
+        if bonus.spellings.iter().any(|v| v.starts_with(&tail)) {
+            len - bonus.at
+        } else {
+            0
+        }
+    }
+
+    /// The key that should be pressed next, following the romaji being typed if there is any.
+    fn expected_key(&self) -> Option<char> {
+        let position = self.position();
+        if let Some(bonus) = &self.char_bonus
+            && bonus.at == position
+        {
+            let pending: String = self.input.chars().skip(position).collect();
+            let next = bonus
+                .spellings
+                .iter()
+                .find(|v| v.starts_with(&pending))
+                .and_then(|v| v.chars().nth(pending.chars().count()));
+            if next.is_some() {
+                return next;
+            }
+        }
+        self.target.chars().nth(position)
+    }
+
+    /// Typed characters that don't match the line, past its end included.
+    fn mismatches(&self) -> usize {
+        let target: Vec<char> = self.target.chars().collect();
+        self.input
+            .chars()
+            .take(self.position())
+            .enumerate()
+            .filter(|(i, c)| target.get(*i) != Some(c))
+            .count()
+    }
+
+    /// Takes the new contents of the input box, typed at `now`.
+    ///
+    /// Romaji that spells the foreign symbol at the cursor is swapped for the symbol, so
+    /// Japanese lines can be typed on a latin keyboard. Depending on the [`Strictness`]
+    /// wrong keys or deletions are refused, leaving the input as it was.
+    pub fn handle_input(&mut self, value: String, now: Instant) -> InputResult {
+        if self.strictness == Strictness::NoBackspace && !value.starts_with(self.input.as_str()) {
+            return InputResult::Typing;
+        }
+        let old_input = self.input.clone();
+        let old_mismatches = self.mismatches();
+        // only single keystrokes go into the key stats, not pastes or edits
+        let expected_key = self.expected_key();
+        let typed_key = value
+            .strip_prefix(old_input.as_str())
+            .filter(|v| v.chars().count() == 1)
+            .and_then(|v| v.chars().next());
+        let added = value
+            .chars()
+            .count()
+            .saturating_sub(self.input.chars().count());
+        if added > 0 {
+            self.line_started.get_or_insert(now);
+        }
+        self.input = value;
+        if self.input.trim().is_empty() {
+            let indent = self.current_indent();
+            self.input = match self.indent_mode {
+                IndentMode::Skip => indent,
+                IndentMode::Require if indent.starts_with(self.input.as_str()) => {
+                    self.input.clone()
+                }
+                IndentMode::Require => "".into(),
+            };
+        }
+        self.line_keystrokes += added;
+
+        if let Some(bonus) = &self.char_bonus
+            && self.input.chars().count() > bonus.at
+            && bonus
+                .spellings
+                .contains(&self.input.chars().skip(bonus.at).collect())
+        {
+            self.input = self.input.chars().take(bonus.at).collect::<String>() + &bonus.symbol;
+            // it's for the symbol that was just typed, until it's checked again
+            self.char_bonus = None;
+        }
+
+        let mismatches = self.mismatches();
+        let new_errors = mismatches.saturating_sub(old_mismatches).min(added);
+        self.line_errors += new_errors;
+        if let (Some(expected), Some(typed)) = (expected_key, typed_key) {
+            let hit = new_errors == 0;
+            self.keys
+                .record(if hit { typed } else { expected }, hit, now);
+        }
+        self.blocked = self.strictness == Strictness::StopOnError && mismatches > old_mismatches;
+        if self.blocked {
+            self.input = old_input;
+            return InputResult::Typing;
+        }
+
+        // there's no line to finish before one is started
+        let finished = !self.target.is_empty()
+            && self.position() >= self.target.chars().count()
+            && (self.strictness != Strictness::StopOnError || mismatches == 0);
+        if finished {
+            let line = CompletedLine {
+                text: self.target.clone(),
+                keystrokes: self.line_keystrokes,
+                errors: self.line_errors,
+                uncorrected: mismatches,
+                duration: self.line_elapsed(now),
+                strictness: self.strictness,
+            };
+            self.completed.push(line.clone());
+            self.line_keystrokes = 0;
+            self.line_errors = 0;
+            self.line_started = None;
+            self.input = "".into();
+            return InputResult::LineCompleted(line);
+        }
+        InputResult::Typing
+    }
+
+    /// Looks up the romaji for the segment of the line at the typing position.
+    pub fn check_foreign_chars(&mut self, chars: &CharController, settings: &RomajiSettings) {
+        let at = self.position();
+        if self.romaji.as_ref().is_none_or(|v| v.0 != *settings) {
+            self.romaji = Some((
+                settings.clone(),
+                chars.romaji_segments(&self.target, settings),
+            ));
+        }
+        self.char_bonus = self
+            .romaji
+            .as_ref()
+            .and_then(|v| v.1.iter().find(|v| v.at == at))
+            .cloned();
+    }
+
+    /// The leading whitespace of the line, which only code has.
+    pub fn current_indent(&self) -> String {
+        self.target
+            .chars()
+            .take_while(|c| c.is_whitespace())
+            .collect()
+    }
+
+    /// What the input holds at the start of a line: nothing, or the indent if it's skipped.
+    fn line_start_input(&self) -> String {
+        match self.indent_mode {
+            IndentMode::Skip => self.current_indent(),
+            IndentMode::Require => "".into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Types `keys` one at a time, a second apart, returning what the last one did.
+    fn type_keys(session: &mut TypingSession, keys: &str, start: Instant) -> InputResult {
+        let mut result = InputResult::Typing;
+        for (i, key) in keys.chars().enumerate() {
+            let value = session.input().to_owned() + &key.to_string();
+            result = session.handle_input(value, start + Duration::from_secs(i as u64 + 1));
+        }
+        result
+    }
+
+    fn session(strictness: Strictness, line: &str) -> TypingSession {
+        let mut session = TypingSession::default();
+        session.set_strictness(strictness);
+        session.start_line(line);
+        session
+    }
+
+    #[test]
+    fn stop_on_error_refuses_wrong_keys() {
+        let now = Instant::now();
+        let mut session = session(Strictness::StopOnError, "ab");
+        assert_eq!(type_keys(&mut session, "x", now), InputResult::Typing);
+        assert_eq!(session.input(), "");
+        assert_eq!(session.line_errors(), 1);
+        let InputResult::LineCompleted(line) = type_keys(&mut session, "ab", now) else {
+            panic!("line not finished");
+        };
+        assert_eq!(line.keystrokes, 3);
+        assert_eq!(line.errors, 1);
+        assert_eq!(line.uncorrected, 0);
+    }
+
+    #[test]
+    fn continue_through_errors_keeps_mistakes() {
+        let now = Instant::now();
+        let mut session = session(Strictness::ContinueThroughErrors, "ab");
+        assert_eq!(type_keys(&mut session, "x", now), InputResult::Typing);
+        assert_eq!(session.input(), "x");
+        let InputResult::LineCompleted(line) = type_keys(&mut session, "b", now) else {
+            panic!("line not finished");
+        };
+        assert_eq!(line.errors, 1);
+        assert_eq!(line.uncorrected, 1);
+    }
+
+    #[test]
+    fn backspace_is_allowed_unless_forbidden() {
+        let now = Instant::now();
+        let mut session = session(Strictness::ContinueThroughErrors, "abc");
+        type_keys(&mut session, "ax", now);
+        session.handle_input("a".into(), now);
+        assert_eq!(session.input(), "a");
+
+        let mut session = self::session(Strictness::NoBackspace, "abc");
+        type_keys(&mut session, "ax", now);
+        assert_eq!(session.handle_input("a".into(), now), InputResult::Typing);
+        assert_eq!(session.input(), "ax");
+    }
+
+    #[test]
+    fn empty_line_never_finishes() {
+        let now = Instant::now();
+        for strictness in [
+            Strictness::StopOnError,
+            Strictness::ContinueThroughErrors,
+            Strictness::NoBackspace,
+        ] {
+            let mut session = session(strictness, "");
+            assert_eq!(type_keys(&mut session, "a", now), InputResult::Typing);
+            assert_eq!(session.completed_stats().lines, 0);
+        }
+    }
+
+    #[test]
+    fn metrics_count_words_of_five_characters() {
+        let metrics = Metrics::new(50, 40, Duration::from_secs(60));
+        assert_eq!(metrics.wpm, 8.0);
+        assert_eq!(metrics.raw_wpm, 10.0);
+        assert_eq!(metrics.cpm, 40.0);
+        assert_eq!(metrics.accuracy, 0.8);
+
+        let metrics = Metrics::new(4, 3, Duration::ZERO);
+        assert_eq!(metrics.wpm, 0.0);
+        assert_eq!(metrics.accuracy, 0.75);
+    }
+
+    #[test]
+    fn session_metrics_cover_finished_lines() {
+        let now = Instant::now();
+        let mut session = session(Strictness::StopOnError, "abcde");
+        // the first keystroke starts the clock, so five keys take four seconds
+        type_keys(&mut session, "abcde", now);
+        let stats = session.stats(now + Duration::from_secs(10));
+        assert_eq!(stats.lines, 1);
+        assert_eq!(stats.keystrokes, 5);
+        assert_eq!(stats.errors, 0);
+        assert_eq!(stats.typing_time, Duration::from_secs(4));
+        let metrics = session.metrics(now);
+        // five correct characters, one word, over four seconds
+        assert!((metrics.wpm - 15.0).abs() < 0.01);
+        assert_eq!(metrics.accuracy, 1.0);
+    }
+}